@@ -1,3 +1,5 @@
+pub mod schema;
 pub mod time;
 
+pub use schema::*;
 pub use time::*;
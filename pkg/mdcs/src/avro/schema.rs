@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+
+use avro_rs::schema::UnionSchema;
+use serde_json::json;
+
+use crate::device::DeviceError;
+
+/// Schema of `AvroSchema` implementations, also used by the code generated
+/// with `#[derive(AvroSchema)]` from the `mdcs_derive` crate.
+pub use avro_rs::schema::Schema;
+
+/// Rust type with a corresponding Avro schema, values of the type are
+/// converted with the avro-rs serde support.
+pub trait AvroSchema {
+    fn avro_schema() -> Result<Schema, DeviceError>;
+}
+
+macro_rules! primitive_schema {
+    ($type:ty, $schema:expr) => {
+        impl AvroSchema for $type {
+            fn avro_schema() -> Result<Schema, DeviceError> {
+                Ok($schema)
+            }
+        }
+    };
+}
+
+primitive_schema!((), Schema::Null);
+primitive_schema!(bool, Schema::Boolean);
+primitive_schema!(i32, Schema::Int);
+primitive_schema!(i64, Schema::Long);
+primitive_schema!(f32, Schema::Float);
+primitive_schema!(f64, Schema::Double);
+primitive_schema!(String, Schema::String);
+
+impl<T: AvroSchema> AvroSchema for Option<T> {
+    fn avro_schema() -> Result<Schema, DeviceError> {
+        let union = UnionSchema::new(vec![Schema::Null, T::avro_schema()?]).map_err(|error| {
+            DeviceError::InternalError(format!("Failed to create optional schema: {}", error))
+        })?;
+
+        Ok(Schema::Union(union))
+    }
+}
+
+impl<T: AvroSchema> AvroSchema for Vec<T> {
+    fn avro_schema() -> Result<Schema, DeviceError> {
+        Ok(Schema::Array(Box::new(T::avro_schema()?)))
+    }
+}
+
+impl<T: AvroSchema> AvroSchema for HashMap<String, T> {
+    fn avro_schema() -> Result<Schema, DeviceError> {
+        Ok(Schema::Map(Box::new(T::avro_schema()?)))
+    }
+}
+
+/// Create the schema of a record with the given fields in order, for use in
+/// `AvroSchema` implementations of structs.
+pub fn record_schema(name: &str, fields: Vec<(&str, Schema)>) -> Result<Schema, DeviceError> {
+    let fields: Vec<serde_json::Value> = fields
+        .into_iter()
+        .map(|(name, schema)| json!({"name": name, "type": schema}))
+        .collect();
+
+    let raw_schema = json!({"type": "record", "name": name, "fields": fields});
+    Schema::parse_str(&raw_schema.to_string()).map_err(|error| {
+        DeviceError::InternalError(format!("Failed to parse record schema {}: {}", name, error))
+    })
+}
+
+/// Create the schema of an enum with the given symbols in order, for use in
+/// `AvroSchema` implementations of enums without data.
+pub fn enum_schema(name: &str, symbols: &[&str]) -> Result<Schema, DeviceError> {
+    let raw_schema = json!({"type": "enum", "name": name, "symbols": symbols});
+    Schema::parse_str(&raw_schema.to_string()).map_err(|error| {
+        DeviceError::InternalError(format!("Failed to parse enum schema {}: {}", name, error))
+    })
+}
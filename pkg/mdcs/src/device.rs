@@ -1,9 +1,27 @@
 pub mod action;
 pub mod attribute;
+pub mod builtin;
+pub mod constraint;
+pub mod derived;
 pub mod device;
 pub mod error;
+pub mod event;
+pub mod identity;
+pub mod job;
+pub mod metadata;
+pub mod path;
+pub mod typed;
 
 pub use action::*;
 pub use attribute::*;
+pub use builtin::*;
+pub use constraint::*;
+pub use derived::*;
 pub use device::*;
 pub use error::*;
+pub use event::*;
+pub use identity::*;
+pub use job::*;
+pub use metadata::*;
+pub use path::*;
+pub use typed::*;
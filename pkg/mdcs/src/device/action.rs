@@ -3,15 +3,45 @@ use std::fmt;
 use avro_rs::schema::Schema;
 use avro_rs::types::Value;
 
+use super::constraint::Constraint;
 use super::error::DeviceError;
+use super::job::JobContext;
+use super::metadata::Metadata;
 
-pub trait Action {
+pub trait Action: Send + Sync {
     fn input_schema(&self) -> Schema;
     fn output_schema(&self) -> Schema;
 
+    /// Schema of the intermediate results emitted while the action runs.
+    fn progress_schema(&self) -> Schema {
+        Schema::Null
+    }
+
+    /// Rules the input must follow, checked before the action is run.
+    fn input_constraints(&self) -> Vec<Constraint> {
+        vec![]
+    }
+
+    /// Description and display hints for the action and its input.
+    fn metadata(&self) -> Metadata {
+        Metadata::default()
+    }
+
+    /// Whether the action may run several times at once, otherwise runs are
+    /// serialized.
+    fn concurrent(&self) -> bool {
+        false
+    }
+
     fn run(&self, _input: Value) -> Result<Value, DeviceError> {
         Err(DeviceError::NotImplemented)
     }
+
+    /// Run the action as a job, long running actions should override this to
+    /// report progress and stop when cancelled.
+    fn run_job(&self, input: Value, _context: &JobContext) -> Result<Value, DeviceError> {
+        self.run(input)
+    }
 }
 
 impl fmt::Debug for Box<dyn Action> {
@@ -19,6 +49,7 @@ impl fmt::Debug for Box<dyn Action> {
         fmt.debug_struct("Action")
             .field("input_schema", &self.input_schema())
             .field("output_schema", &self.output_schema())
+            .field("progress_schema", &self.progress_schema())
             .finish()
     }
 }
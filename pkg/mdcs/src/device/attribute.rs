@@ -1,10 +1,13 @@
 use std::collections::HashSet;
 use std::fmt;
+use std::sync::Arc;
 
 use avro_rs::schema::Schema;
 use avro_rs::types::Value;
 
+use super::constraint::Constraint;
 use super::error::DeviceError;
+use super::metadata::Metadata;
 
 #[derive(PartialEq, Eq, Hash, Debug)]
 pub enum AttributeFlags {
@@ -12,7 +15,36 @@ pub enum AttributeFlags {
     Write,
 }
 
-pub trait Attribute {
+/// Handle an attribute uses to report a new value without being polled.
+#[derive(Clone)]
+pub struct AttributeNotifier {
+    callback: Arc<dyn Fn(Value) + Send + Sync>,
+}
+
+impl AttributeNotifier {
+    pub fn new<F>(callback: F) -> AttributeNotifier
+    where
+        F: Fn(Value) + Send + Sync + 'static,
+    {
+        AttributeNotifier {
+            callback: Arc::new(callback),
+        }
+    }
+
+    /// Report the current value of the attribute, it must match the
+    /// attribute schema.
+    pub fn notify(&self, value: Value) {
+        (self.callback)(value);
+    }
+}
+
+impl fmt::Debug for AttributeNotifier {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("AttributeNotifier").finish()
+    }
+}
+
+pub trait Attribute: Send + Sync {
     fn schema(&self) -> Schema;
 
     fn readable(&self) -> bool {
@@ -37,6 +69,22 @@ pub trait Attribute {
         return flags;
     }
 
+    /// Rules written values must follow, checked before `write` is called.
+    fn constraints(&self) -> Vec<Constraint> {
+        vec![]
+    }
+
+    /// Description, unit and display hints for the attribute.
+    fn metadata(&self) -> Metadata {
+        Metadata::default()
+    }
+
+    /// Whether the attribute may be accessed from several threads at the same
+    /// time, otherwise calls are serialized.
+    fn concurrent(&self) -> bool {
+        false
+    }
+
     fn read(&self) -> Result<Value, DeviceError> {
         Err(DeviceError::NotImplemented)
     }
@@ -44,6 +92,14 @@ pub trait Attribute {
     fn write(&self, _value: Value) -> Result<(), DeviceError> {
         Err(DeviceError::NotImplemented)
     }
+
+    /// Start reporting value changes to the notifier, attributes that cannot
+    /// detect changes return `DeviceError::NotImplemented` and have to be
+    /// polled instead. The plugin server calls this at most once per
+    /// attribute.
+    fn watch(&self, _notifier: AttributeNotifier) -> Result<(), DeviceError> {
+        Err(DeviceError::NotImplemented)
+    }
 }
 
 impl fmt::Debug for Box<dyn Attribute> {
@@ -0,0 +1,386 @@
+use std::fmt;
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+
+use avro_rs::schema::Schema;
+use avro_rs::types::Value;
+
+use super::action::Action;
+use super::attribute::{Attribute, AttributeNotifier};
+use super::error::DeviceError;
+use super::metadata::Metadata;
+
+type ReadFn = dyn Fn() -> Result<Value, DeviceError> + Send + Sync;
+type RunFn = dyn Fn(Value) -> Result<Value, DeviceError> + Send + Sync;
+
+/// Read only attribute that always has the same value.
+#[derive(Debug)]
+pub struct ConstantAttribute {
+    schema: Schema,
+    value: Value,
+    metadata: Metadata,
+}
+
+impl ConstantAttribute {
+    pub fn new(schema: Schema, value: Value) -> ConstantAttribute {
+        ConstantAttribute {
+            schema,
+            value,
+            metadata: Metadata::default(),
+        }
+    }
+
+    pub fn with_metadata(mut self, metadata: Metadata) -> ConstantAttribute {
+        self.metadata = metadata;
+        self
+    }
+}
+
+impl Attribute for ConstantAttribute {
+    fn schema(&self) -> Schema {
+        self.schema.clone()
+    }
+
+    fn readable(&self) -> bool {
+        true
+    }
+
+    fn concurrent(&self) -> bool {
+        true
+    }
+
+    fn metadata(&self) -> Metadata {
+        self.metadata.clone()
+    }
+
+    fn read(&self) -> Result<Value, DeviceError> {
+        Ok(self.value.clone())
+    }
+}
+
+/// Readable and writable attribute that keeps its value in memory. Clones
+/// share the value so device code can keep one to update it.
+#[derive(Clone)]
+pub struct ValueAttribute {
+    schema: Schema,
+    value: Arc<RwLock<Value>>,
+    notifiers: Arc<Mutex<Vec<AttributeNotifier>>>,
+    metadata: Metadata,
+}
+
+impl ValueAttribute {
+    pub fn new(schema: Schema, value: Value) -> ValueAttribute {
+        ValueAttribute {
+            schema,
+            value: Arc::new(RwLock::new(value)),
+            notifiers: Arc::new(Mutex::new(vec![])),
+            metadata: Metadata::default(),
+        }
+    }
+
+    pub fn with_metadata(mut self, metadata: Metadata) -> ValueAttribute {
+        self.metadata = metadata;
+        self
+    }
+
+    pub fn get(&self) -> Value {
+        self.value
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Replace the value and report it to the watchers of the attribute.
+    pub fn set(&self, value: Value) {
+        *self.value.write().unwrap_or_else(PoisonError::into_inner) = value.clone();
+
+        let notifiers = self
+            .notifiers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        for notifier in notifiers.iter() {
+            notifier.notify(value.clone());
+        }
+    }
+}
+
+impl fmt::Debug for ValueAttribute {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("ValueAttribute")
+            .field("schema", &self.schema)
+            .field("value", &self.get())
+            .finish()
+    }
+}
+
+impl Attribute for ValueAttribute {
+    fn schema(&self) -> Schema {
+        self.schema.clone()
+    }
+
+    fn readable(&self) -> bool {
+        true
+    }
+
+    fn writable(&self) -> bool {
+        true
+    }
+
+    fn concurrent(&self) -> bool {
+        true
+    }
+
+    fn metadata(&self) -> Metadata {
+        self.metadata.clone()
+    }
+
+    fn read(&self) -> Result<Value, DeviceError> {
+        Ok(self.get())
+    }
+
+    fn write(&self, value: Value) -> Result<(), DeviceError> {
+        self.set(value);
+        Ok(())
+    }
+
+    fn watch(&self, notifier: AttributeNotifier) -> Result<(), DeviceError> {
+        self.notifiers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(notifier);
+
+        Ok(())
+    }
+}
+
+/// Read only attribute whose value is computed by a closure on every read.
+pub struct ComputedAttribute {
+    schema: Schema,
+    read: Box<ReadFn>,
+    metadata: Metadata,
+}
+
+impl ComputedAttribute {
+    pub fn new<F>(schema: Schema, read: F) -> ComputedAttribute
+    where
+        F: Fn() -> Result<Value, DeviceError> + Send + Sync + 'static,
+    {
+        ComputedAttribute {
+            schema,
+            read: Box::new(read),
+            metadata: Metadata::default(),
+        }
+    }
+
+    pub fn with_metadata(mut self, metadata: Metadata) -> ComputedAttribute {
+        self.metadata = metadata;
+        self
+    }
+}
+
+impl fmt::Debug for ComputedAttribute {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("ComputedAttribute")
+            .field("schema", &self.schema)
+            .finish()
+    }
+}
+
+impl Attribute for ComputedAttribute {
+    fn schema(&self) -> Schema {
+        self.schema.clone()
+    }
+
+    fn readable(&self) -> bool {
+        true
+    }
+
+    fn metadata(&self) -> Metadata {
+        self.metadata.clone()
+    }
+
+    fn read(&self) -> Result<Value, DeviceError> {
+        (self.read)()
+    }
+}
+
+/// Action that runs a closure with its input.
+pub struct FnAction {
+    input_schema: Schema,
+    output_schema: Schema,
+    run: Box<RunFn>,
+    metadata: Metadata,
+}
+
+impl FnAction {
+    pub fn new<F>(input_schema: Schema, output_schema: Schema, run: F) -> FnAction
+    where
+        F: Fn(Value) -> Result<Value, DeviceError> + Send + Sync + 'static,
+    {
+        FnAction {
+            input_schema,
+            output_schema,
+            run: Box::new(run),
+            metadata: Metadata::default(),
+        }
+    }
+
+    pub fn with_metadata(mut self, metadata: Metadata) -> FnAction {
+        self.metadata = metadata;
+        self
+    }
+}
+
+impl fmt::Debug for FnAction {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("FnAction")
+            .field("input_schema", &self.input_schema)
+            .field("output_schema", &self.output_schema)
+            .finish()
+    }
+}
+
+impl Action for FnAction {
+    fn input_schema(&self) -> Schema {
+        self.input_schema.clone()
+    }
+
+    fn output_schema(&self) -> Schema {
+        self.output_schema.clone()
+    }
+
+    fn metadata(&self) -> Metadata {
+        self.metadata.clone()
+    }
+
+    fn run(&self, input: Value) -> Result<Value, DeviceError> {
+        (self.run)(input)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicI64, Ordering};
+
+    use crate::device::AttributeFlags;
+
+    #[test]
+    fn constant_attribute() {
+        let metadata = Metadata {
+            unit: Some("V".to_string()),
+            ..Metadata::default()
+        };
+
+        let attribute =
+            ConstantAttribute::new(Schema::Long, Value::Long(5)).with_metadata(metadata.clone());
+
+        assert_eq!(attribute.schema(), Schema::Long);
+        assert_eq!(
+            attribute.flags(),
+            vec![AttributeFlags::Read].into_iter().collect()
+        );
+        assert_eq!(attribute.metadata(), metadata);
+        assert_eq!(attribute.read().unwrap(), Value::Long(5));
+
+        match attribute.write(Value::Long(6)) {
+            Err(DeviceError::NotImplemented) => {}
+            result => panic!("unexpected write result: {:?}", result),
+        }
+
+        assert_eq!(attribute.read().unwrap(), Value::Long(5));
+    }
+
+    #[test]
+    fn value_attribute() {
+        let attribute = ValueAttribute::new(Schema::Long, Value::Long(1));
+        assert_eq!(
+            attribute.flags(),
+            vec![AttributeFlags::Read, AttributeFlags::Write]
+                .into_iter()
+                .collect()
+        );
+
+        assert_eq!(attribute.read().unwrap(), Value::Long(1));
+        attribute.write(Value::Long(2)).unwrap();
+        assert_eq!(attribute.read().unwrap(), Value::Long(2));
+
+        // clones share the value
+        let handle = attribute.clone();
+        handle.set(Value::Long(3));
+        assert_eq!(attribute.read().unwrap(), Value::Long(3));
+        assert_eq!(handle.get(), Value::Long(3));
+    }
+
+    #[test]
+    fn value_attribute_notifies_watchers() {
+        let attribute = ValueAttribute::new(Schema::Long, Value::Long(0));
+        let values = Arc::new(Mutex::new(vec![]));
+
+        let received = values.clone();
+        attribute
+            .watch(AttributeNotifier::new(move |value| {
+                received.lock().unwrap().push(value)
+            }))
+            .unwrap();
+
+        attribute.write(Value::Long(1)).unwrap();
+        attribute.clone().set(Value::Long(2));
+
+        assert_eq!(
+            *values.lock().unwrap(),
+            vec![Value::Long(1), Value::Long(2)]
+        );
+    }
+
+    #[test]
+    fn computed_attribute() {
+        let counter = Arc::new(AtomicI64::new(0));
+        let reads = counter.clone();
+        let attribute = ComputedAttribute::new(Schema::Long, move || {
+            match reads.fetch_add(1, Ordering::SeqCst) {
+                count if count < 2 => Ok(Value::Long(count)),
+                _ => Err(DeviceError::from("Sensor disconnected")),
+            }
+        });
+
+        assert_eq!(
+            attribute.flags(),
+            vec![AttributeFlags::Read].into_iter().collect()
+        );
+        assert_eq!(attribute.read().unwrap(), Value::Long(0));
+        assert_eq!(attribute.read().unwrap(), Value::Long(1));
+
+        match attribute.read() {
+            Err(DeviceError::InternalError(message)) => {
+                assert_eq!(message, "Sensor disconnected")
+            }
+            result => panic!("unexpected read result: {:?}", result),
+        }
+
+        match attribute.write(Value::Long(0)) {
+            Err(DeviceError::NotImplemented) => {}
+            result => panic!("unexpected write result: {:?}", result),
+        }
+
+        assert_eq!(counter.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn fn_action() {
+        let action = FnAction::new(Schema::Long, Schema::Long, |input| match input {
+            Value::Long(value) => Ok(Value::Long(value * 2)),
+            _ => Err(DeviceError::ActionRunInvalid("expected a long".to_string())),
+        });
+
+        assert_eq!(action.input_schema(), Schema::Long);
+        assert_eq!(action.output_schema(), Schema::Long);
+        assert_eq!(action.run(Value::Long(21)).unwrap(), Value::Long(42));
+
+        match action.run(Value::Null) {
+            Err(DeviceError::ActionRunInvalid(message)) => assert_eq!(message, "expected a long"),
+            result => panic!("unexpected run result: {:?}", result),
+        }
+    }
+}
@@ -0,0 +1,511 @@
+use std::fmt;
+use std::result::Result;
+
+use avro_rs::types::Value;
+use regex::Regex;
+
+use super::error::DeviceError;
+
+/// Rule a value written to an attribute or passed to an action must follow,
+/// checked by the plugin server before the member is called.
+#[derive(Debug, Clone)]
+pub enum Constraint {
+    /// Finite number within inclusive bounds.
+    Range {
+        minimum: Option<f64>,
+        maximum: Option<f64>,
+    },
+    /// String matching a regular expression.
+    Pattern(Regex),
+    /// Enum symbol or string from a fixed set.
+    Allowed(Vec<String>),
+    /// Number of items in an array or map, characters in a string or bytes
+    /// in a binary value within inclusive bounds.
+    Length {
+        minimum: Option<usize>,
+        maximum: Option<usize>,
+    },
+    /// Constraints on a field of a record.
+    Field {
+        name: String,
+        constraints: Vec<Constraint>,
+    },
+}
+
+/// Value that does not follow a constraint.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    /// Dot separated record fields leading to the offending value, `None`
+    /// when it is the value itself.
+    pub field: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.field {
+            Some(ref field) => write!(fmt, "{}: {}", field, self.message),
+            None => write!(fmt, "{}", self.message),
+        }
+    }
+}
+
+impl Violation {
+    fn new(message: String) -> Violation {
+        Violation {
+            field: None,
+            message,
+        }
+    }
+
+    fn within(mut self, name: &str) -> Violation {
+        self.field = Some(match self.field {
+            Some(field) => format!("{}.{}", name, field),
+            None => name.to_string(),
+        });
+
+        self
+    }
+}
+
+/// Number checked against a range, integers are kept exact since converting
+/// them to floating point loses precision above 2^53.
+#[derive(Debug, Clone, Copy)]
+enum Number {
+    Integer(i64),
+    Float(f64),
+}
+
+/// Smallest floating point number above every `i64`.
+const INTEGER_LIMIT: f64 = 9_223_372_036_854_775_808.0;
+
+impl Number {
+    fn less_than(self, bound: f64) -> bool {
+        match self {
+            // conversions saturate, which only matters at the upper limit
+            Number::Integer(number) => {
+                bound.ceil() >= INTEGER_LIMIT || number < bound.ceil() as i64
+            }
+            Number::Float(number) => number < bound,
+        }
+    }
+
+    fn greater_than(self, bound: f64) -> bool {
+        match self {
+            Number::Integer(number) => {
+                bound.floor() < -INTEGER_LIMIT || number > bound.floor() as i64
+            }
+            Number::Float(number) => number > bound,
+        }
+    }
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Number::Integer(number) => write!(fmt, "{}", number),
+            Number::Float(number) => write!(fmt, "{}", number),
+        }
+    }
+}
+
+fn number(value: &Value) -> Option<Number> {
+    match value {
+        Value::Int(value) => Some(Number::Integer(i64::from(*value))),
+        Value::Long(value) => Some(Number::Integer(*value)),
+        Value::Float(value) => Some(Number::Float(f64::from(*value))),
+        Value::Double(value) => Some(Number::Float(*value)),
+        _ => None,
+    }
+}
+
+fn length(value: &Value) -> Option<usize> {
+    match value {
+        Value::String(value) => Some(value.chars().count()),
+        Value::Bytes(value) | Value::Fixed(_, value) => Some(value.len()),
+        Value::Array(items) => Some(items.len()),
+        Value::Map(items) => Some(items.len()),
+        _ => None,
+    }
+}
+
+impl Constraint {
+    pub fn range(minimum: Option<f64>, maximum: Option<f64>) -> Constraint {
+        Constraint::Range { minimum, maximum }
+    }
+
+    pub fn pattern(pattern: &str) -> Result<Constraint, DeviceError> {
+        // anchored so the whole string has to match
+        let regex = Regex::new(&format!("^(?:{})$", pattern)).map_err(|error| {
+            DeviceError::InternalError(format!("Invalid constraint pattern: {}", error))
+        })?;
+
+        Ok(Constraint::Pattern(regex))
+    }
+
+    pub fn allowed<S: ToString>(values: &[S]) -> Constraint {
+        Constraint::Allowed(values.iter().map(ToString::to_string).collect())
+    }
+
+    pub fn length(minimum: Option<usize>, maximum: Option<usize>) -> Constraint {
+        Constraint::Length { minimum, maximum }
+    }
+
+    pub fn field(name: &str, constraints: Vec<Constraint>) -> Constraint {
+        Constraint::Field {
+            name: name.to_string(),
+            constraints,
+        }
+    }
+
+    /// Check a decoded value, optional values that are absent always pass.
+    pub fn check(&self, value: &Value) -> Result<(), Violation> {
+        let value = match value {
+            Value::Union(inner) => &**inner,
+            value => value,
+        };
+
+        if let Value::Null = value {
+            return Ok(());
+        }
+
+        match self {
+            Constraint::Range { minimum, maximum } => {
+                let number = number(value)
+                    .ok_or_else(|| Violation::new("Value is not a number".to_string()))?;
+
+                // NaN compares false with every bound
+                if let Number::Float(float) = number {
+                    if !float.is_finite() {
+                        return Err(Violation::new(format!(
+                            "Value {} is not a finite number",
+                            float
+                        )));
+                    }
+                }
+
+                if let Some(minimum) = minimum {
+                    if number.less_than(*minimum) {
+                        return Err(Violation::new(format!(
+                            "Value {} is less than {}",
+                            number, minimum
+                        )));
+                    }
+                }
+
+                if let Some(maximum) = maximum {
+                    if number.greater_than(*maximum) {
+                        return Err(Violation::new(format!(
+                            "Value {} is greater than {}",
+                            number, maximum
+                        )));
+                    }
+                }
+            }
+            Constraint::Pattern(regex) => match value {
+                Value::String(string) if regex.is_match(string) => {}
+                Value::String(string) => {
+                    return Err(Violation::new(format!(
+                        "Value {:?} does not match {}",
+                        string,
+                        regex.as_str()
+                    )));
+                }
+                _ => return Err(Violation::new("Value is not a string".to_string())),
+            },
+            Constraint::Allowed(allowed) => {
+                let symbol = match value {
+                    Value::String(string) => string,
+                    Value::Enum(_, symbol) => symbol,
+                    _ => {
+                        return Err(Violation::new("Value is not a string or enum".to_string()));
+                    }
+                };
+
+                if !allowed.contains(symbol) {
+                    return Err(Violation::new(format!(
+                        "Value {:?} is not one of {}",
+                        symbol,
+                        allowed.join(", ")
+                    )));
+                }
+            }
+            Constraint::Length { minimum, maximum } => {
+                let length = length(value)
+                    .ok_or_else(|| Violation::new("Value does not have a length".to_string()))?;
+
+                if let Some(minimum) = minimum {
+                    if length < *minimum {
+                        return Err(Violation::new(format!(
+                            "Length {} is less than {}",
+                            length, minimum
+                        )));
+                    }
+                }
+
+                if let Some(maximum) = maximum {
+                    if length > *maximum {
+                        return Err(Violation::new(format!(
+                            "Length {} is greater than {}",
+                            length, maximum
+                        )));
+                    }
+                }
+            }
+            Constraint::Field { name, constraints } => {
+                let fields = match value {
+                    Value::Record(fields) => fields,
+                    _ => return Err(Violation::new("Value is not a record".to_string())),
+                };
+
+                let field = fields
+                    .iter()
+                    .find(|(field, _value)| field == name)
+                    .map(|(_field, value)| value)
+                    .ok_or_else(|| Violation::new("Field not found".to_string()).within(name))?;
+
+                validate(constraints, field).map_err(|violation| violation.within(name))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Check a decoded value against every constraint, stopping at the first
+/// violation.
+pub fn validate(constraints: &[Constraint], value: &Value) -> Result<(), Violation> {
+    for constraint in constraints {
+        constraint.check(value)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    fn message(result: Result<(), Violation>) -> String {
+        result.unwrap_err().message
+    }
+
+    fn record(fields: Vec<(&str, Value)>) -> Value {
+        Value::Record(
+            fields
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn range() {
+        let range = Constraint::range(Some(-1.0), Some(1.5));
+        assert!(range.check(&Value::Int(-1)).is_ok());
+        assert!(range.check(&Value::Long(1)).is_ok());
+        assert!(range.check(&Value::Float(1.5)).is_ok());
+        assert!(range.check(&Value::Double(0.0)).is_ok());
+
+        assert_eq!(
+            message(range.check(&Value::Int(-2))),
+            "Value -2 is less than -1"
+        );
+        assert_eq!(
+            message(range.check(&Value::Double(1.6))),
+            "Value 1.6 is greater than 1.5"
+        );
+        assert_eq!(
+            message(range.check(&Value::String("1".to_string()))),
+            "Value is not a number"
+        );
+
+        let unbounded = Constraint::range(None, None);
+        assert!(unbounded.check(&Value::Long(i64::MAX)).is_ok());
+    }
+
+    #[test]
+    fn range_compares_integers_exactly() {
+        // 2^53 + 1 rounds down to the bound as a double
+        let range = Constraint::range(None, Some(9_007_199_254_740_992.0));
+        assert!(range.check(&Value::Long(9_007_199_254_740_992)).is_ok());
+        assert_eq!(
+            message(range.check(&Value::Long(9_007_199_254_740_993))),
+            "Value 9007199254740993 is greater than 9007199254740992"
+        );
+
+        let fractional = Constraint::range(Some(0.5), Some(2.5));
+        assert!(fractional.check(&Value::Int(0)).is_err());
+        assert!(fractional.check(&Value::Int(1)).is_ok());
+        assert!(fractional.check(&Value::Int(2)).is_ok());
+        assert!(fractional.check(&Value::Int(3)).is_err());
+
+        // bounds beyond the integer range
+        let above = Constraint::range(Some(1e19), None);
+        assert!(above.check(&Value::Long(i64::MAX)).is_err());
+        let below = Constraint::range(None, Some(-1e19));
+        assert!(below.check(&Value::Long(i64::MIN)).is_err());
+        let wide = Constraint::range(Some(-1e19), Some(1e19));
+        assert!(wide.check(&Value::Long(i64::MIN)).is_ok());
+        assert!(wide.check(&Value::Long(i64::MAX)).is_ok());
+    }
+
+    #[test]
+    fn range_rejects_non_finite_numbers() {
+        for constraint in &[
+            Constraint::range(Some(0.0), Some(1.0)),
+            Constraint::range(None, None),
+        ] {
+            for number in &[f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+                assert!(constraint.check(&Value::Double(*number)).is_err());
+            }
+
+            assert!(constraint.check(&Value::Float(f32::NAN)).is_err());
+        }
+    }
+
+    #[test]
+    fn pattern() {
+        let pattern = Constraint::pattern("[a-z]+[0-9]?").unwrap();
+        assert!(pattern.check(&Value::String("temp".to_string())).is_ok());
+        assert!(pattern.check(&Value::String("temp1".to_string())).is_ok());
+
+        // the whole string has to match
+        assert_eq!(
+            message(pattern.check(&Value::String("temp12".to_string()))),
+            "Value \"temp12\" does not match ^(?:[a-z]+[0-9]?)$"
+        );
+        assert!(pattern.check(&Value::String("1temp".to_string())).is_err());
+        assert_eq!(
+            message(pattern.check(&Value::Int(1))),
+            "Value is not a string"
+        );
+
+        assert!(Constraint::pattern("(").is_err());
+    }
+
+    #[test]
+    fn allowed() {
+        let allowed = Constraint::allowed(&["Low", "High"]);
+        assert!(allowed.check(&Value::String("Low".to_string())).is_ok());
+        assert!(allowed.check(&Value::Enum(1, "High".to_string())).is_ok());
+
+        assert_eq!(
+            message(allowed.check(&Value::Enum(2, "Off".to_string()))),
+            "Value \"Off\" is not one of Low, High"
+        );
+        assert!(allowed.check(&Value::String("low".to_string())).is_err());
+        assert_eq!(
+            message(allowed.check(&Value::Boolean(true))),
+            "Value is not a string or enum"
+        );
+    }
+
+    #[test]
+    fn length() {
+        let length = Constraint::length(Some(1), Some(3));
+        assert!(length.check(&Value::String("abc".to_string())).is_ok());
+        assert!(length.check(&Value::String("äöü".to_string())).is_ok());
+        assert!(length.check(&Value::Bytes(vec![1])).is_ok());
+        assert!(length.check(&Value::Fixed(2, vec![1, 2])).is_ok());
+        assert!(length.check(&Value::Array(vec![Value::Null])).is_ok());
+
+        let mut map = HashMap::new();
+        map.insert("a".to_string(), Value::Null);
+        assert!(length.check(&Value::Map(map)).is_ok());
+
+        assert_eq!(
+            message(length.check(&Value::String(String::new()))),
+            "Length 0 is less than 1"
+        );
+        assert_eq!(
+            message(length.check(&Value::Bytes(vec![0; 4]))),
+            "Length 4 is greater than 3"
+        );
+        assert_eq!(
+            message(length.check(&Value::Long(1))),
+            "Value does not have a length"
+        );
+    }
+
+    #[test]
+    fn absent_optional_values_pass() {
+        let range = Constraint::range(Some(0.0), None);
+        assert!(range.check(&Value::Null).is_ok());
+        assert!(range.check(&Value::Union(Box::new(Value::Null))).is_ok());
+        assert!(range.check(&Value::Union(Box::new(Value::Int(1)))).is_ok());
+        assert!(range
+            .check(&Value::Union(Box::new(Value::Int(-1))))
+            .is_err());
+    }
+
+    #[test]
+    fn field() {
+        let constraint = Constraint::field(
+            "settings",
+            vec![Constraint::field(
+                "rate",
+                vec![Constraint::range(Some(1.0), Some(10.0))],
+            )],
+        );
+
+        let value = |rate| record(vec![("settings", record(vec![("rate", Value::Int(rate))]))]);
+        assert!(constraint.check(&value(5)).is_ok());
+
+        // nested violations name the path to the offending field
+        assert_eq!(
+            constraint.check(&value(11)),
+            Err(Violation {
+                field: Some("settings.rate".to_string()),
+                message: "Value 11 is greater than 10".to_string(),
+            })
+        );
+
+        assert_eq!(
+            constraint.check(&record(vec![("settings", record(vec![]))])),
+            Err(Violation {
+                field: Some("settings.rate".to_string()),
+                message: "Field not found".to_string(),
+            })
+        );
+
+        assert_eq!(
+            constraint.check(&Value::Int(1)),
+            Err(Violation {
+                field: None,
+                message: "Value is not a record".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn validate_stops_at_first_violation() {
+        let constraints = vec![
+            Constraint::length(None, Some(2)),
+            Constraint::pattern("[0-9]+").unwrap(),
+        ];
+
+        assert!(validate(&constraints, &Value::String("12".to_string())).is_ok());
+        assert_eq!(
+            message(validate(&constraints, &Value::String("123".to_string()))),
+            "Length 3 is greater than 2"
+        );
+        assert_eq!(
+            message(validate(&constraints, &Value::String("ab".to_string()))),
+            "Value \"ab\" does not match ^(?:[0-9]+)$"
+        );
+        assert!(validate(&[], &Value::Null).is_ok());
+    }
+
+    #[test]
+    fn display_violation() {
+        let violation = Violation::new("Value is not a number".to_string());
+        assert_eq!(violation.to_string(), "Value is not a number");
+        assert_eq!(
+            violation.within("rate").within("settings").to_string(),
+            "settings.rate: Value is not a number"
+        );
+    }
+}
@@ -0,0 +1,179 @@
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use serde::Serialize;
+
+use super::device::Device;
+use super::error::DeviceError;
+use super::job::JobContext;
+use super::metadata::Metadata;
+use super::typed::{TypedActionHandler, TypedAttributeHandler, TypedJobContext};
+
+/// Type that can be turned into a device, usually implemented with
+/// `#[derive(Device)]` from the `mdcs_derive` crate.
+pub trait IntoDevice {
+    fn into_device(self) -> Result<Device, DeviceError>;
+}
+
+/// Type with methods exposed as device actions, usually implemented with
+/// `#[actions]` from the `mdcs_derive` crate.
+pub trait DeviceActions: Sized {
+    fn register_actions(state: &Arc<Mutex<Self>>, device: &Device) -> Result<(), DeviceError>;
+}
+
+fn lock<S>(state: &Mutex<S>) -> MutexGuard<'_, S> {
+    // a panic in a member marks it as faulted but the state remains usable
+    state.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Attribute backed by a field of a struct shared by the members of a
+/// derived device.
+pub struct FieldAttribute<S, T> {
+    state: Arc<Mutex<S>>,
+    field: fn(&mut S) -> &mut T,
+    readable: bool,
+    writable: bool,
+    metadata: Metadata,
+}
+
+impl<S, T> FieldAttribute<S, T> {
+    pub fn new(state: Arc<Mutex<S>>, field: fn(&mut S) -> &mut T) -> FieldAttribute<S, T> {
+        FieldAttribute {
+            state,
+            field,
+            readable: false,
+            writable: false,
+            metadata: Metadata::default(),
+        }
+    }
+
+    pub fn readable(mut self, readable: bool) -> FieldAttribute<S, T> {
+        self.readable = readable;
+        self
+    }
+
+    pub fn writable(mut self, writable: bool) -> FieldAttribute<S, T> {
+        self.writable = writable;
+        self
+    }
+
+    pub fn with_metadata(mut self, metadata: Metadata) -> FieldAttribute<S, T> {
+        self.metadata = metadata;
+        self
+    }
+}
+
+impl<S, T> fmt::Debug for FieldAttribute<S, T> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("FieldAttribute")
+            .field("readable", &self.readable)
+            .field("writable", &self.writable)
+            .finish()
+    }
+}
+
+impl<S, T> TypedAttributeHandler<T> for FieldAttribute<S, T>
+where
+    S: Send,
+    T: Clone,
+{
+    fn readable(&self) -> bool {
+        self.readable
+    }
+
+    fn writable(&self) -> bool {
+        self.writable
+    }
+
+    fn metadata(&self) -> Metadata {
+        self.metadata.clone()
+    }
+
+    fn read(&self) -> Result<T, DeviceError> {
+        let mut state = lock(&self.state);
+        Ok((self.field)(&mut state).clone())
+    }
+
+    fn write(&self, value: T) -> Result<(), DeviceError> {
+        let mut state = lock(&self.state);
+        *(self.field)(&mut state) = value;
+        Ok(())
+    }
+}
+
+/// Method called by a derived action, long running ones also take the
+/// context of the job.
+enum ActionMethod<S, In, Out, Progress> {
+    Run(fn(&mut S, In) -> Result<Out, DeviceError>),
+    Job(fn(&mut S, In, &TypedJobContext<Progress>) -> Result<Out, DeviceError>),
+}
+
+/// Action backed by a method of a struct shared by the members of a derived
+/// device.
+pub struct MethodAction<S, In, Out, Progress = ()> {
+    state: Arc<Mutex<S>>,
+    method: ActionMethod<S, In, Out, Progress>,
+    metadata: Metadata,
+}
+
+impl<S, In, Out> MethodAction<S, In, Out> {
+    pub fn new(
+        state: Arc<Mutex<S>>,
+        method: fn(&mut S, In) -> Result<Out, DeviceError>,
+    ) -> MethodAction<S, In, Out> {
+        MethodAction {
+            state,
+            method: ActionMethod::Run(method),
+            metadata: Metadata::default(),
+        }
+    }
+}
+
+impl<S, In, Out, Progress> MethodAction<S, In, Out, Progress> {
+    /// Wrap a method that reports progress, emits intermediate results or
+    /// stops when cancelled.
+    pub fn with_job(
+        state: Arc<Mutex<S>>,
+        method: fn(&mut S, In, &TypedJobContext<Progress>) -> Result<Out, DeviceError>,
+    ) -> MethodAction<S, In, Out, Progress> {
+        MethodAction {
+            state,
+            method: ActionMethod::Job(method),
+            metadata: Metadata::default(),
+        }
+    }
+
+    pub fn with_metadata(mut self, metadata: Metadata) -> MethodAction<S, In, Out, Progress> {
+        self.metadata = metadata;
+        self
+    }
+}
+
+impl<S, In, Out, Progress> fmt::Debug for MethodAction<S, In, Out, Progress> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("MethodAction").finish()
+    }
+}
+
+impl<S, In, Out, Progress> TypedActionHandler<In, Out, Progress>
+    for MethodAction<S, In, Out, Progress>
+where
+    S: Send,
+    Progress: Serialize,
+{
+    fn metadata(&self) -> Metadata {
+        self.metadata.clone()
+    }
+
+    fn run(&self, input: In) -> Result<Out, DeviceError> {
+        self.run_job(input, &TypedJobContext::new(JobContext::new()))
+    }
+
+    fn run_job(&self, input: In, context: &TypedJobContext<Progress>) -> Result<Out, DeviceError> {
+        let mut state = lock(&self.state);
+        match self.method {
+            ActionMethod::Run(method) => method(&mut state, input),
+            ActionMethod::Job(method) => method(&mut state, input, context),
+        }
+    }
+}
@@ -1,14 +1,22 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::fmt;
+use std::ptr;
 use std::result::Result;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError, RwLock, RwLockReadGuard};
+use std::vec;
 
 use super::action::Action;
 use super::attribute::Attribute;
 use super::error::DeviceError;
+use super::event::Event;
+use super::identity::Identity;
+use super::path::{Path, PATH_SEPARATOR};
 
 pub enum Member {
     Attribute(Box<dyn Attribute>),
     Action(Box<dyn Action>),
+    Event(Box<dyn Event>),
 }
 
 impl fmt::Debug for Member {
@@ -16,47 +24,672 @@ impl fmt::Debug for Member {
         match self {
             Member::Attribute(attribute) => fmt.debug_tuple("Attribute").field(attribute).finish(),
             Member::Action(action) => fmt.debug_tuple("Action").field(action).finish(),
+            Member::Event(event) => fmt.debug_tuple("Event").field(event).finish(),
         }
     }
 }
 
+/// Serializes mounts so that checking for cycles and attaching the device
+/// cannot interleave with another mount.
+static MOUNTING: Mutex<()> = Mutex::new(());
+
+/// Device mounted in the tree of another one, which stops following its
+/// changes once it is detached.
+#[derive(Debug)]
+struct Mount {
+    device: Arc<Device>,
+    notifier: DeviceNotifier,
+    attached: Arc<AtomicBool>,
+}
+
+impl Drop for Mount {
+    fn drop(&mut self) {
+        // a change reported before the notifier is removed may still arrive
+        self.attached.store(false, Ordering::SeqCst);
+        self.device.unwatch(&self.notifier);
+    }
+}
+
+/// Entry in the device tree, members and mounted devices are always leaves.
+#[derive(Debug)]
+enum Node {
+    Member(Arc<Member>),
+    Branch(BTreeMap<String, Node>),
+    Mount(Mount),
+}
+
+/// Node at a path, or the mounted device the rest of the path lies in.
+enum Found<'a, 'b> {
+    Node(&'a Node),
+    Mounted(&'a Device, &'b [String]),
+}
+
+impl Node {
+    fn find<'a, 'b>(&'a self, segments: &'b [String]) -> Option<Found<'a, 'b>> {
+        let mut node = self;
+        for (index, segment) in segments.iter().enumerate() {
+            node = match node {
+                Node::Branch(children) => children.get(segment)?,
+                Node::Member(_) => return None,
+                Node::Mount(mount) => {
+                    return Some(Found::Mounted(&mount.device, &segments[index..]))
+                }
+            };
+        }
+
+        match node {
+            Node::Mount(mount) => Some(Found::Mounted(&mount.device, &[])),
+            node => Some(Found::Node(node)),
+        }
+    }
+
+    /// Detach the node at a path relative to this one, branches left empty
+    /// are removed as well.
+    fn take(&mut self, segments: &[String]) -> Option<Node> {
+        // members of mounted devices are removed through their own device
+        let children = match self {
+            Node::Branch(children) => children,
+            _ => return None,
+        };
+
+        let (segment, rest) = segments.split_first()?;
+        if rest.is_empty() {
+            return children.remove(segment);
+        }
+
+        let child = children.get_mut(segment)?;
+        let taken = child.take(rest)?;
+        if let Node::Branch(grandchildren) = child {
+            if grandchildren.is_empty() {
+                children.remove(segment);
+            }
+        }
+
+        Some(taken)
+    }
+
+    /// Add a node at a path creating the branches leading to it, members and
+    /// branches that already exist are never replaced.
+    fn attach(&mut self, path: &Path, new_node: Node) -> Result<(), DeviceError> {
+        let exists = || DeviceError::PathExists(path.to_string());
+        let (name, parent) = match (path.name(), path.parent()) {
+            (Some(name), Some(parent)) => (name.to_string(), parent),
+            _ => return Err(exists()),
+        };
+
+        let mut node = self;
+        for segment in parent.segments() {
+            node = match node {
+                Node::Branch(children) => children
+                    .entry(segment.clone())
+                    .or_insert_with(|| Node::Branch(BTreeMap::new())),
+                _ => return Err(exists()),
+            };
+        }
+
+        match node {
+            Node::Branch(children) if !children.contains_key(&name) => {
+                children.insert(name, new_node);
+                Ok(())
+            }
+            _ => Err(exists()),
+        }
+    }
+
+    /// Collect the devices mounted at or below this node.
+    fn mounted(&self, devices: &mut Vec<Arc<Device>>) {
+        match self {
+            Node::Member(_) => {}
+            Node::Mount(mount) => devices.push(mount.device.clone()),
+            Node::Branch(children) => {
+                for child in children.values() {
+                    child.mounted(devices);
+                }
+            }
+        }
+    }
+
+    /// Collect the members at or below this node in path order.
+    fn collect(&self, path: String, members: &mut Vec<(String, Arc<Member>)>) {
+        match self {
+            Node::Member(member) => members.push((path, member.clone())),
+            Node::Mount(mount) => {
+                mount.device.collect(&[], path, members);
+            }
+            Node::Branch(children) => {
+                for (segment, child) in children.iter() {
+                    let child_path = if path.is_empty() {
+                        segment.clone()
+                    } else {
+                        format!("{}{}{}", path, PATH_SEPARATOR, segment)
+                    };
+
+                    child.collect(child_path, members);
+                }
+            }
+        }
+    }
+}
+
+/// Members of a device tree in path order along with their full paths, taken
+/// when the iterator was created.
+#[derive(Debug)]
+pub struct Members {
+    members: vec::IntoIter<(String, Arc<Member>)>,
+    version: u64,
+}
+
+impl Members {
+    /// Version of the device the members were taken from.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+}
+
+impl Iterator for Members {
+    type Item = (String, Arc<Member>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.members.next()
+    }
+}
+
+/// Handle a device uses to report that members were added or removed.
+#[derive(Clone)]
+pub struct DeviceNotifier {
+    callback: Arc<dyn Fn(u64) + Send + Sync>,
+}
+
+impl DeviceNotifier {
+    pub fn new<F>(callback: F) -> DeviceNotifier
+    where
+        F: Fn(u64) + Send + Sync + 'static,
+    {
+        DeviceNotifier {
+            callback: Arc::new(callback),
+        }
+    }
+
+    /// Report the version of the device after a change.
+    pub fn notify(&self, version: u64) {
+        (self.callback)(version);
+    }
+}
+
+impl fmt::Debug for DeviceNotifier {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("DeviceNotifier").finish()
+    }
+}
+
+/// Members and identity of a device along with the number of changes made
+/// to them.
+#[derive(Debug)]
+struct Tree {
+    root: Node,
+    identity: Identity,
+    version: u64,
+}
+
+/// Tree of members that can be shared between threads behind an `Arc`.
+///
+/// Members may be inserted and removed at any time, lookups and iteration
+/// see the tree either before or after each change. Retrieved members are
+/// reference counted, so a caller that is still using a removed member can
+/// finish with it. Every change, including to its identity, increments the
+/// version of the device and is reported to its watchers.
 #[derive(Debug)]
 pub struct Device {
-    members: HashMap<String, Member>,
+    tree: Arc<RwLock<Tree>>,
+    notifiers: Arc<Mutex<Vec<DeviceNotifier>>>,
+}
+
+/// Apply a change to a tree and report the new version to the watchers once
+/// it is visible.
+fn apply<F>(
+    tree: &RwLock<Tree>,
+    notifiers: &Mutex<Vec<DeviceNotifier>>,
+    change: F,
+) -> Result<(), DeviceError>
+where
+    F: FnOnce(&mut Tree) -> Result<(), DeviceError>,
+{
+    let version = {
+        let mut tree = tree.write().unwrap_or_else(PoisonError::into_inner);
+        change(&mut tree)?;
+        tree.version += 1;
+        tree.version
+    };
+
+    let notifiers = notifiers
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone();
+
+    for notifier in notifiers.iter() {
+        notifier.notify(version);
+    }
+
+    Ok(())
 }
 
 impl Device {
     pub fn new() -> Device {
         Device {
-            members: HashMap::new(),
+            tree: Arc::new(RwLock::new(Tree {
+                root: Node::Branch(BTreeMap::new()),
+                identity: Identity::default(),
+                version: 0,
+            })),
+            notifiers: Arc::new(Mutex::new(vec![])),
         }
     }
 
-    pub fn get(&self, path: &str) -> Option<&Member> {
-        self.members.get(path)
+    fn read_tree(&self) -> RwLockReadGuard<'_, Tree> {
+        // the tree is never left half modified so a poisoned lock is safe
+        self.tree.read().unwrap_or_else(PoisonError::into_inner)
     }
 
-    pub fn insert(&mut self, path: &str, member: Member) -> Result<(), DeviceError> {
-        let path = String::from(path);
-        if self.members.contains_key(&path) {
-            Err(DeviceError::PathExists(path))
-        } else {
-            self.members.insert(path, member);
-            Ok(())
+    fn change<F>(&self, change: F) -> Result<(), DeviceError>
+    where
+        F: FnOnce(&mut Tree) -> Result<(), DeviceError>,
+    {
+        apply(&self.tree, &self.notifiers, change)
+    }
+
+    /// Whether this device is `device` or has it mounted at any depth.
+    fn contains(&self, device: &Device) -> bool {
+        if ptr::eq(self, device) {
+            return true;
+        }
+
+        let mut mounted = vec![];
+        self.read_tree().root.mounted(&mut mounted);
+        mounted.iter().any(|child| child.contains(device))
+    }
+
+    fn member(&self, segments: &[String]) -> Option<Arc<Member>> {
+        match self.read_tree().root.find(segments)? {
+            Found::Node(Node::Member(member)) => Some(member.clone()),
+            Found::Node(_) => None,
+            Found::Mounted(device, rest) => device.member(rest),
         }
     }
 
-    pub fn remove(&mut self, path: &str) -> Result<(), DeviceError> {
-        let path = String::from(path);
-        if self.members.contains_key(&path) {
-            self.members.remove(&path);
+    fn child_names(&self, segments: &[String]) -> Option<Vec<String>> {
+        match self.read_tree().root.find(segments)? {
+            Found::Node(Node::Branch(children)) => Some(children.keys().cloned().collect()),
+            Found::Node(_) => None,
+            Found::Mounted(device, rest) => device.child_names(rest),
+        }
+    }
+
+    /// Collect the members at or below a path, returns whether it exists.
+    fn collect(
+        &self,
+        segments: &[String],
+        path: String,
+        members: &mut Vec<(String, Arc<Member>)>,
+    ) -> bool {
+        match self.read_tree().root.find(segments) {
+            Some(Found::Node(node)) => {
+                node.collect(path, members);
+                true
+            }
+            Some(Found::Mounted(device, rest)) => device.collect(rest, path, members),
+            None => false,
+        }
+    }
+
+    /// Number of changes made to the members of the device.
+    pub fn version(&self) -> u64 {
+        self.read_tree().version
+    }
+
+    /// Report every change made to the members of the device.
+    pub fn watch(&self, notifier: DeviceNotifier) {
+        self.notifiers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(notifier);
+    }
+
+    /// Stop reporting changes to a notifier passed to `watch`.
+    fn unwatch(&self, notifier: &DeviceNotifier) {
+        self.notifiers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .retain(|watcher| !Arc::ptr_eq(&watcher.callback, &notifier.callback));
+    }
+
+    pub fn identity(&self) -> Identity {
+        self.read_tree().identity.clone()
+    }
+
+    pub fn set_identity(&self, identity: Identity) {
+        // replacing the identity cannot fail
+        let _ = self.change(|tree| {
+            tree.identity = identity;
             Ok(())
-        } else {
-            Err(DeviceError::PathNotFound(path))
+        });
+    }
+
+    pub fn get(&self, path: &str) -> Option<Arc<Member>> {
+        let path = Path::parse(path).ok()?;
+        self.member(path.segments())
+    }
+
+    pub fn insert(&self, path: &str, member: Member) -> Result<(), DeviceError> {
+        let parsed = Path::parse(path)?;
+        self.change(|tree| tree.root.attach(&parsed, Node::Member(Arc::new(member))))
+    }
+
+    /// Remove the member or the whole branch at a path.
+    pub fn remove(&self, path: &str) -> Result<(), DeviceError> {
+        let parsed = Path::parse(path)?;
+        self.change(|tree| match tree.root.take(parsed.segments()) {
+            Some(_) => Ok(()),
+            None => Err(DeviceError::PathNotFound(path.to_string())),
+        })
+    }
+
+    /// Place another device under a prefix of this one, its identity is not
+    /// kept. Its members can still be changed through the device itself,
+    /// which also counts as a change of this one, and removing the prefix
+    /// detaches it again.
+    pub fn mount(&self, prefix: &str, device: Arc<Device>) -> Result<(), DeviceError> {
+        let parsed = Path::parse(prefix)?;
+        if parsed.is_root() {
+            return Err(DeviceError::PathExists(prefix.to_string()));
+        }
+
+        let _mounting = MOUNTING.lock().unwrap_or_else(PoisonError::into_inner);
+        if device.contains(self) {
+            return Err(DeviceError::from("Device cannot be mounted inside itself"));
+        }
+
+        let attached = Arc::new(AtomicBool::new(true));
+        let following = attached.clone();
+        let tree = Arc::downgrade(&self.tree);
+        let notifiers = Arc::downgrade(&self.notifiers);
+        let notifier = DeviceNotifier::new(move |_version| {
+            if !following.load(Ordering::SeqCst) {
+                return;
+            }
+
+            if let (Some(tree), Some(notifiers)) = (tree.upgrade(), notifiers.upgrade()) {
+                let _ = apply(&tree, &notifiers, |_tree| Ok(()));
+            }
+        });
+
+        device.watch(notifier.clone());
+
+        // a mount that fails to attach is dropped which detaches it
+        let mount = Mount {
+            device,
+            notifier,
+            attached,
+        };
+
+        self.change(|tree| tree.root.attach(&parsed, Node::Mount(mount)))
+    }
+
+    /// Names of the members and branches directly below a prefix.
+    pub fn children(&self, prefix: &str) -> Result<Vec<String>, DeviceError> {
+        let parsed = Path::parse(prefix)?;
+        self.child_names(parsed.segments())
+            .ok_or_else(|| DeviceError::PathNotFound(prefix.to_string()))
+    }
+
+    /// Members at or below a path, which may also refer to a single member.
+    pub fn subtree(&self, prefix: &str) -> Result<Members, DeviceError> {
+        let parsed = Path::parse(prefix)?;
+
+        // mounted devices count their changes after making them, so the
+        // members are never older than the version
+        let version = self.version();
+        let mut members = vec![];
+        if !self.collect(parsed.segments(), parsed.to_string(), &mut members) {
+            return Err(DeviceError::PathNotFound(prefix.to_string()));
+        }
+
+        Ok(Members {
+            members: members.into_iter(),
+            version,
+        })
+    }
+
+    pub fn iter(&self) -> Members {
+        let version = self.version();
+        let mut members = vec![];
+        self.collect(&[], String::new(), &mut members);
+        Members {
+            members: members.into_iter(),
+            version,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::thread;
+
+    use avro_rs::types::Value;
+    use avro_rs::Schema;
+
+    use crate::device::ConstantAttribute;
+
+    fn constant(value: i64) -> Member {
+        Member::Attribute(Box::new(ConstantAttribute::new(
+            Schema::Long,
+            Value::Long(value),
+        )))
+    }
+
+    fn paths(members: Members) -> Vec<String> {
+        members.map(|(path, _member)| path).collect()
+    }
+
+    fn device(paths: &[&str]) -> Device {
+        let device = Device::new();
+        for path in paths {
+            device.insert(path, constant(0)).unwrap();
         }
+
+        device
     }
 
-    pub fn iter(&self) -> std::collections::hash_map::Iter<String, Member> {
-        self.members.iter()
+    #[test]
+    fn insert_and_get() {
+        let device = device(&["a", "b.c", "b.d.e"]);
+        assert!(device.get("a").is_some());
+        assert!(device.get("b.d.e").is_some());
+
+        // branches and missing or invalid paths are not members
+        assert!(device.get("b").is_none());
+        assert!(device.get("").is_none());
+        assert!(device.get("b.x").is_none());
+        assert!(device.get("a.x").is_none());
+        assert!(device.get("b..c").is_none());
+    }
+
+    #[test]
+    fn insert_existing_or_invalid_path() {
+        let device = device(&["a", "b.c"]);
+
+        for path in &["a", "b", "b.c", "a.x", ""] {
+            match device.insert(path, constant(1)) {
+                Err(DeviceError::PathExists(existing)) => assert_eq!(&existing, path),
+                result => panic!("inserted {:?}: {:?}", path, result),
+            }
+        }
+
+        match device.insert("b.", constant(1)) {
+            Err(DeviceError::InvalidPath(_)) => {}
+            result => panic!("inserted invalid path: {:?}", result),
+        }
+
+        assert_eq!(paths(device.iter()), ["a", "b.c"]);
+    }
+
+    #[test]
+    fn remove_members_and_branches() {
+        let device = device(&["a", "b.c", "b.d.e", "b.d.f"]);
+
+        device.remove("b.d.e").unwrap();
+        assert_eq!(paths(device.iter()), ["a", "b.c", "b.d.f"]);
+
+        // removing the last member of a branch removes the branch as well
+        device.remove("b.d.f").unwrap();
+        assert!(device.children("b.d").is_err());
+        assert_eq!(device.children("b").unwrap(), ["c"]);
+
+        device.remove("b").unwrap();
+        assert_eq!(paths(device.iter()), ["a"]);
+
+        match device.remove("b") {
+            Err(DeviceError::PathNotFound(path)) => assert_eq!(path, "b"),
+            result => panic!("removed missing path: {:?}", result),
+        }
+
+        assert!(device.remove("a.x").is_err());
+    }
+
+    #[test]
+    fn children() {
+        let device = device(&["z", "b.c", "b.d.e", "a"]);
+        assert_eq!(device.children("").unwrap(), ["a", "b", "z"]);
+        assert_eq!(device.children("b").unwrap(), ["c", "d"]);
+        assert!(device.children("a").is_err());
+        assert!(device.children("x").is_err());
+    }
+
+    #[test]
+    fn subtree() {
+        let device = device(&["a", "b.c", "b.d.e", "bc"]);
+        assert_eq!(
+            paths(device.subtree("").unwrap()),
+            ["a", "b.c", "b.d.e", "bc"]
+        );
+        assert_eq!(paths(device.subtree("b").unwrap()), ["b.c", "b.d.e"]);
+        assert_eq!(paths(device.subtree("b.c").unwrap()), ["b.c"]);
+        assert!(device.subtree("b.x").is_err());
+        assert!(device.subtree("b.").is_err());
+    }
+
+    #[test]
+    fn mount_keeps_following_the_device() {
+        let device = device(&["a"]);
+        let chip = Arc::new(self::device(&["temp1"]));
+        device.mount("chips.one", chip.clone()).unwrap();
+        assert_eq!(paths(device.iter()), ["a", "chips.one.temp1"]);
+
+        // members added through the handle appear below the prefix
+        chip.insert("temp2", constant(0)).unwrap();
+        assert!(device.get("chips.one.temp2").is_some());
+        assert_eq!(device.children("chips.one").unwrap(), ["temp1", "temp2"]);
+        assert_eq!(
+            paths(device.subtree("chips.one.temp2").unwrap()),
+            ["chips.one.temp2"]
+        );
+
+        // members of the mounted device are changed through the device itself
+        assert!(device.insert("chips.one.temp3", constant(0)).is_err());
+        assert!(device.remove("chips.one.temp1").is_err());
+
+        device.remove("chips.one").unwrap();
+        assert_eq!(paths(device.iter()), ["a"]);
+        assert_eq!(paths(chip.iter()), ["temp1", "temp2"]);
+    }
+
+    #[test]
+    fn changes_bump_the_version_and_notify_watchers() {
+        let device = Device::new();
+        let versions = Arc::new(Mutex::new(vec![]));
+
+        let reported = versions.clone();
+        device.watch(DeviceNotifier::new(move |version| {
+            reported.lock().unwrap().push(version)
+        }));
+
+        assert_eq!(device.version(), 0);
+        device.insert("a", constant(0)).unwrap();
+        device.insert("b.c", constant(0)).unwrap();
+        device.remove("a").unwrap();
+        device.set_identity(Identity::default());
+        assert_eq!(device.version(), 4);
+        assert_eq!(*versions.lock().unwrap(), [1, 2, 3, 4]);
+
+        // rejected changes leave the version alone
+        assert!(device.insert("b.c", constant(0)).is_err());
+        assert!(device.remove("x").is_err());
+        assert_eq!(device.version(), 4);
+        assert_eq!(device.iter().version(), 4);
+        assert_eq!(*versions.lock().unwrap(), [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn mounted_changes_bump_the_version() {
+        let device = Device::new();
+        let chip = Arc::new(Device::new());
+        device.mount("chip", chip.clone()).unwrap();
+
+        let versions = Arc::new(Mutex::new(vec![]));
+        let reported = versions.clone();
+        device.watch(DeviceNotifier::new(move |version| {
+            reported.lock().unwrap().push(version)
+        }));
+
+        chip.insert("temp", constant(0)).unwrap();
+        assert_eq!(*versions.lock().unwrap(), [2]);
+
+        // the device no longer follows a removed mount
+        device.remove("chip").unwrap();
+        chip.insert("other", constant(0)).unwrap();
+        assert_eq!(*versions.lock().unwrap(), [2, 3]);
+        assert_eq!(device.version(), 3);
+        assert!(chip.notifiers.lock().unwrap().is_empty());
+
+        // mounts that fail to attach do not leave a notifier behind
+        device.insert("taken", constant(0)).unwrap();
+        assert!(device.mount("taken", chip.clone()).is_err());
+        assert!(chip.notifiers.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn mount_rejects_cycles_and_root() {
+        let outer = Arc::new(device(&[]));
+        let inner = Arc::new(device(&["x"]));
+        outer.mount("inner", inner.clone()).unwrap();
+
+        assert!(inner.mount("outer", outer.clone()).is_err());
+        assert!(inner.mount("inner", inner.clone()).is_err());
+        assert!(outer.mount("", Arc::new(device(&[]))).is_err());
+        assert!(outer.mount("inner", Arc::new(device(&[]))).is_err());
+    }
+
+    #[test]
+    fn concurrent_mounts_do_not_form_cycles() {
+        for _ in 0..100 {
+            let first = Arc::new(Device::new());
+            let second = Arc::new(Device::new());
+
+            let mounts: Vec<_> = vec![
+                (first.clone(), second.clone()),
+                (second.clone(), first.clone()),
+            ]
+            .into_iter()
+            .map(|(outer, inner)| thread::spawn(move || outer.mount("inner", inner).is_ok()))
+            .collect();
+
+            let mounted: Vec<bool> = mounts
+                .into_iter()
+                .map(|mount| mount.join().unwrap())
+                .collect();
+
+            assert_eq!(mounted.iter().filter(|mounted| **mounted).count(), 1);
+        }
     }
 }
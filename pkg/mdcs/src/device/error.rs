@@ -7,11 +7,13 @@ pub enum DeviceError {
     Io(io::Error),
     InternalError(String),
     NotImplemented,
+    Cancelled,
     ActionRunInvalid(String),
     AttributeReadInvalid(String),
     AttributeWriteInvalid(String),
     PathExists(String),
     PathNotFound(String),
+    InvalidPath(String),
 }
 
 impl fmt::Display for DeviceError {
@@ -20,6 +22,7 @@ impl fmt::Display for DeviceError {
             DeviceError::Io(ref error) => write!(fmt, "IO error: {}", error),
             DeviceError::InternalError(msg) => write!(fmt, "Internal error: {}", msg),
             DeviceError::NotImplemented => write!(fmt, "Method not implemented"),
+            DeviceError::Cancelled => write!(fmt, "Cancelled"),
             DeviceError::ActionRunInvalid(path) => write!(fmt, "Action cannot be run: {}", path),
             DeviceError::AttributeReadInvalid(path) => {
                 write!(fmt, "Attribute cannot be read: {}", path)
@@ -29,6 +32,7 @@ impl fmt::Display for DeviceError {
             }
             DeviceError::PathExists(path) => write!(fmt, "Device path already exists: {}", path),
             DeviceError::PathNotFound(path) => write!(fmt, "Device path not found: {}", path),
+            DeviceError::InvalidPath(path) => write!(fmt, "Invalid device path: {}", path),
         }
     }
 }
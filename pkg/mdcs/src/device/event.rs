@@ -0,0 +1,105 @@
+use std::fmt;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use avro_rs::schema::Schema;
+use avro_rs::types::Value;
+
+use super::error::DeviceError;
+
+/// Handle an event uses to deliver its occurrences to listeners.
+#[derive(Clone)]
+pub struct EventEmitter {
+    callback: Arc<dyn Fn(Value) + Send + Sync>,
+}
+
+impl EventEmitter {
+    pub fn new<F>(callback: F) -> EventEmitter
+    where
+        F: Fn(Value) + Send + Sync + 'static,
+    {
+        EventEmitter {
+            callback: Arc::new(callback),
+        }
+    }
+
+    /// Report an occurrence of the event, the payload must match the event
+    /// schema.
+    pub fn emit(&self, payload: Value) {
+        (self.callback)(payload);
+    }
+}
+
+impl fmt::Debug for EventEmitter {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("EventEmitter").finish()
+    }
+}
+
+/// Discrete occurrence such as a button press or a limit switch trip that is
+/// neither a value that can be read nor an action that can be run.
+pub trait Event: Send + Sync {
+    /// Schema of the payload of each occurrence.
+    fn schema(&self) -> Schema;
+
+    /// Start delivering occurrences to the emitter. The plugin server calls
+    /// this at most once per event.
+    fn listen(&self, emitter: EventEmitter) -> Result<(), DeviceError>;
+}
+
+impl fmt::Debug for Box<dyn Event> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("Event")
+            .field("schema", &self.schema())
+            .finish()
+    }
+}
+
+/// Event implementation that device code can keep a clone of and emit
+/// occurrences through.
+#[derive(Clone)]
+pub struct EventSource {
+    schema: Schema,
+    emitters: Arc<Mutex<Vec<EventEmitter>>>,
+}
+
+impl EventSource {
+    pub fn new(schema: Schema) -> EventSource {
+        EventSource {
+            schema,
+            emitters: Arc::new(Mutex::new(vec![])),
+        }
+    }
+
+    /// Deliver an occurrence to every listener.
+    pub fn emit(&self, payload: Value) {
+        let emitters = self
+            .emitters
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+
+        for emitter in emitters {
+            emitter.emit(payload.clone());
+        }
+    }
+}
+
+impl fmt::Debug for EventSource {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("EventSource")
+            .field("schema", &self.schema)
+            .finish()
+    }
+}
+
+impl Event for EventSource {
+    fn schema(&self) -> Schema {
+        self.schema.clone()
+    }
+
+    fn listen(&self, emitter: EventEmitter) -> Result<(), DeviceError> {
+        let mut emitters = self.emitters.lock().unwrap_or_else(PoisonError::into_inner);
+        emitters.push(emitter);
+        Ok(())
+    }
+}
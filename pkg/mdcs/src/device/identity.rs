@@ -0,0 +1,18 @@
+/// What a device is, reported along with its members so the node can keep an
+/// inventory of the equipment it controls.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Identity {
+    pub name: Option<String>,
+    pub vendor: Option<String>,
+    pub model: Option<String>,
+    /// Serial number or other identifier unique to the unit.
+    pub serial: Option<String>,
+    pub firmware_version: Option<String>,
+    pub description: Option<String>,
+}
+
+impl Identity {
+    pub fn new() -> Identity {
+        Identity::default()
+    }
+}
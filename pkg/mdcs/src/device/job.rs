@@ -0,0 +1,72 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use avro_rs::types::Value;
+
+type EventSink = Arc<dyn Fn(Value) + Send + Sync>;
+
+/// Shared state of an action run that allows it to report progress, emit
+/// intermediate results, and observe cancellation requests.
+#[derive(Clone, Default)]
+pub struct JobContext {
+    cancelled: Arc<AtomicBool>,
+    progress: Arc<Mutex<f64>>,
+    sink: Option<EventSink>,
+}
+
+impl fmt::Debug for JobContext {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("JobContext")
+            .field("cancelled", &self.is_cancelled())
+            .field("progress", &self.progress())
+            .finish()
+    }
+}
+
+impl JobContext {
+    pub fn new() -> JobContext {
+        JobContext::default()
+    }
+
+    /// Create a context that passes the events emitted by the action to the
+    /// given function.
+    pub fn with_sink<F>(sink: F) -> JobContext
+    where
+        F: Fn(Value) + Send + Sync + 'static,
+    {
+        JobContext {
+            sink: Some(Arc::new(sink)),
+            ..JobContext::default()
+        }
+    }
+
+    /// Request the action to stop as soon as possible.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether the action has been asked to stop, long running actions should
+    /// check this periodically and return `DeviceError::Cancelled`.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Report the fraction of the work completed so far, between 0 and 1.
+    pub fn set_progress(&self, progress: f64) {
+        let mut current = self.progress.lock().unwrap_or_else(PoisonError::into_inner);
+        *current = progress.clamp(0.0, 1.0);
+    }
+
+    pub fn progress(&self) -> f64 {
+        *self.progress.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Emit an intermediate result while the action is running, the value
+    /// must match the action progress schema.
+    pub fn emit(&self, event: Value) {
+        if let Some(ref sink) = self.sink {
+            sink(event);
+        }
+    }
+}
@@ -0,0 +1,24 @@
+use std::collections::BTreeMap;
+
+/// Optional details about a member meant for operators and user interfaces,
+/// none of them are enforced by the plugin server.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metadata {
+    pub description: Option<String>,
+    /// Engineering unit of the value, such as `degC` or `V`.
+    pub unit: Option<String>,
+    pub minimum: Option<f64>,
+    pub maximum: Option<f64>,
+    /// Smallest meaningful change of the value.
+    pub step: Option<f64>,
+    /// Display text for enum symbols or particular values.
+    pub labels: BTreeMap<String, String>,
+    /// Number of decimal places to display.
+    pub precision: Option<u32>,
+}
+
+impl Metadata {
+    pub fn new() -> Metadata {
+        Metadata::default()
+    }
+}
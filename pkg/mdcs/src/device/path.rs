@@ -0,0 +1,159 @@
+use std::fmt;
+use std::result::Result;
+use std::str::FromStr;
+
+use super::error::DeviceError;
+
+/// Character that separates the segments of a member path.
+pub const PATH_SEPARATOR: char = '.';
+
+/// Location of a member or branch in a device tree, written as segments
+/// separated by dots such as `coretemp.temp1`. The empty path is the root.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Path {
+    segments: Vec<String>,
+}
+
+impl Path {
+    pub fn root() -> Path {
+        Path::default()
+    }
+
+    /// Parse a path, every segment must be a non-empty sequence of ASCII
+    /// letters, digits, underscores or dashes.
+    pub fn parse(path: &str) -> Result<Path, DeviceError> {
+        if path.is_empty() {
+            return Ok(Path::root());
+        }
+
+        let mut segments: Vec<String> = vec![];
+        for segment in path.split(PATH_SEPARATOR) {
+            if !Path::is_valid_segment(segment) {
+                return Err(DeviceError::InvalidPath(path.to_string()));
+            }
+
+            segments.push(segment.to_string());
+        }
+
+        Ok(Path { segments })
+    }
+
+    pub fn is_valid_segment(segment: &str) -> bool {
+        !segment.is_empty()
+            && segment
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    pub fn segments(&self) -> &[String] {
+        &self.segments
+    }
+
+    /// Last segment of the path, `None` for the root.
+    pub fn name(&self) -> Option<&str> {
+        self.segments.last().map(String::as_str)
+    }
+
+    /// Path without its last segment, `None` for the root.
+    pub fn parent(&self) -> Option<Path> {
+        if self.is_root() {
+            return None;
+        }
+
+        Some(Path {
+            segments: self.segments[..self.segments.len() - 1].to_vec(),
+        })
+    }
+
+    /// Path of a direct child of this one.
+    pub fn child(&self, segment: &str) -> Result<Path, DeviceError> {
+        if !Path::is_valid_segment(segment) {
+            return Err(DeviceError::InvalidPath(segment.to_string()));
+        }
+
+        let mut segments = self.segments.clone();
+        segments.push(segment.to_string());
+        Ok(Path { segments })
+    }
+
+    /// Path of `other` relative to this one.
+    pub fn join(&self, other: &Path) -> Path {
+        let mut segments = self.segments.clone();
+        segments.extend(other.segments.iter().cloned());
+        Path { segments }
+    }
+
+    /// Whether this path is `prefix` or lies below it.
+    pub fn starts_with(&self, prefix: &Path) -> bool {
+        self.segments.starts_with(&prefix.segments)
+    }
+}
+
+impl fmt::Display for Path {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let separator = PATH_SEPARATOR.to_string();
+        write!(fmt, "{}", self.segments.join(&separator))
+    }
+}
+
+impl FromStr for Path {
+    type Err = DeviceError;
+
+    fn from_str(path: &str) -> Result<Path, DeviceError> {
+        Path::parse(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_valid_paths() {
+        assert!(Path::parse("").unwrap().is_root());
+
+        let path = Path::parse("coretemp-isa-0000.temp_1").unwrap();
+        assert_eq!(path.segments(), ["coretemp-isa-0000", "temp_1"]);
+        assert_eq!(path.to_string(), "coretemp-isa-0000.temp_1");
+        assert_eq!("coretemp-isa-0000.temp_1".parse::<Path>().unwrap(), path);
+    }
+
+    #[test]
+    fn reject_invalid_paths() {
+        for path in &[".", "a.", ".a", "a..b", "a b", "a/b", "tempé", "a.*"] {
+            match Path::parse(path) {
+                Err(DeviceError::InvalidPath(invalid)) => assert_eq!(&invalid, path),
+                result => panic!("{:?} parsed as {:?}", path, result),
+            }
+        }
+    }
+
+    #[test]
+    fn navigate_paths() {
+        let path = Path::parse("a.b.c").unwrap();
+        assert_eq!(path.name(), Some("c"));
+        assert_eq!(path.parent(), Some(Path::parse("a.b").unwrap()));
+        assert_eq!(Path::root().name(), None);
+        assert_eq!(Path::root().parent(), None);
+
+        let child = path.child("d").unwrap();
+        assert_eq!(child.to_string(), "a.b.c.d");
+        assert!(path.child("d.e").is_err());
+        assert!(path.child("").is_err());
+
+        let joined = Path::parse("x").unwrap().join(&path);
+        assert_eq!(joined.to_string(), "x.a.b.c");
+
+        assert!(child.starts_with(&path));
+        assert!(path.starts_with(&path));
+        assert!(path.starts_with(&Path::root()));
+        assert!(!path.starts_with(&child));
+        assert!(!Path::parse("a.bc")
+            .unwrap()
+            .starts_with(&Path::parse("a.b").unwrap()));
+    }
+}
@@ -0,0 +1,531 @@
+use std::fmt;
+use std::marker::PhantomData;
+
+use avro_rs::schema::Schema;
+use avro_rs::types::Value;
+use avro_rs::{from_value, to_value};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use super::action::Action;
+use super::attribute::Attribute;
+use super::constraint::Constraint;
+use super::error::DeviceError;
+use super::job::JobContext;
+use super::metadata::Metadata;
+use crate::avro::AvroSchema;
+
+/// Attribute implemented in terms of a plain Rust value type, wrap it in a
+/// `TypedAttribute` to add it to a device.
+pub trait TypedAttributeHandler<T>: Send + Sync {
+    fn readable(&self) -> bool {
+        false
+    }
+
+    fn writable(&self) -> bool {
+        false
+    }
+
+    fn concurrent(&self) -> bool {
+        false
+    }
+
+    fn constraints(&self) -> Vec<Constraint> {
+        vec![]
+    }
+
+    fn metadata(&self) -> Metadata {
+        Metadata::default()
+    }
+
+    fn read(&self) -> Result<T, DeviceError> {
+        Err(DeviceError::NotImplemented)
+    }
+
+    fn write(&self, _value: T) -> Result<(), DeviceError> {
+        Err(DeviceError::NotImplemented)
+    }
+}
+
+/// Job context of a typed action, which emits intermediate results of its
+/// progress type.
+#[derive(Debug, Clone)]
+pub struct TypedJobContext<Progress> {
+    context: JobContext,
+    progress: PhantomData<fn(Progress)>,
+}
+
+impl<Progress: Serialize> TypedJobContext<Progress> {
+    pub fn new(context: JobContext) -> TypedJobContext<Progress> {
+        TypedJobContext {
+            context,
+            progress: PhantomData,
+        }
+    }
+
+    /// Untyped context of the job.
+    pub fn context(&self) -> &JobContext {
+        &self.context
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.context.is_cancelled()
+    }
+
+    pub fn set_progress(&self, progress: f64) {
+        self.context.set_progress(progress);
+    }
+
+    /// Emit an intermediate result while the action is running.
+    pub fn emit(&self, event: Progress) -> Result<(), DeviceError> {
+        self.context.emit(encode_value(event)?);
+        Ok(())
+    }
+}
+
+/// Action implemented in terms of plain Rust input, output and progress
+/// types, wrap it in a `TypedAction` to add it to a device.
+pub trait TypedActionHandler<In, Out, Progress = ()>: Send + Sync {
+    fn concurrent(&self) -> bool {
+        false
+    }
+
+    fn input_constraints(&self) -> Vec<Constraint> {
+        vec![]
+    }
+
+    fn metadata(&self) -> Metadata {
+        Metadata::default()
+    }
+
+    fn run(&self, input: In) -> Result<Out, DeviceError>;
+
+    /// Run the action as a job, long running actions should override this to
+    /// report progress, emit intermediate results and stop when cancelled.
+    fn run_job(&self, input: In, _context: &TypedJobContext<Progress>) -> Result<Out, DeviceError> {
+        self.run(input)
+    }
+}
+
+/// Convert a value to the Avro representation of its type.
+pub fn encode_value<T: Serialize>(value: T) -> Result<Value, DeviceError> {
+    to_value(value)
+        .map_err(|error| DeviceError::InternalError(format!("Failed to encode value: {}", error)))
+}
+
+/// Convert an Avro value to a Rust type.
+pub fn decode_value<T: DeserializeOwned>(value: &Value) -> Result<T, DeviceError> {
+    from_value::<T>(value)
+        .map_err(|error| DeviceError::InternalError(format!("Failed to decode value: {}", error)))
+}
+
+/// Attribute with a schema derived from its value type.
+pub struct TypedAttribute<T> {
+    handler: Box<dyn TypedAttributeHandler<T>>,
+    schema: Schema,
+}
+
+impl<T: AvroSchema> TypedAttribute<T> {
+    /// Wrap a handler, fails when the schema of the value type is invalid.
+    pub fn new<H>(handler: H) -> Result<TypedAttribute<T>, DeviceError>
+    where
+        H: TypedAttributeHandler<T> + 'static,
+    {
+        Ok(TypedAttribute {
+            handler: Box::new(handler),
+            schema: T::avro_schema()?,
+        })
+    }
+}
+
+impl<T> fmt::Debug for TypedAttribute<T> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("TypedAttribute")
+            .field("schema", &self.schema)
+            .finish()
+    }
+}
+
+impl<T> Attribute for TypedAttribute<T>
+where
+    T: Serialize + DeserializeOwned,
+{
+    fn schema(&self) -> Schema {
+        self.schema.clone()
+    }
+
+    fn readable(&self) -> bool {
+        self.handler.readable()
+    }
+
+    fn writable(&self) -> bool {
+        self.handler.writable()
+    }
+
+    fn concurrent(&self) -> bool {
+        self.handler.concurrent()
+    }
+
+    fn constraints(&self) -> Vec<Constraint> {
+        self.handler.constraints()
+    }
+
+    fn metadata(&self) -> Metadata {
+        self.handler.metadata()
+    }
+
+    fn read(&self) -> Result<Value, DeviceError> {
+        encode_value(self.handler.read()?)
+    }
+
+    fn write(&self, value: Value) -> Result<(), DeviceError> {
+        let value = decode_value::<T>(&value).map_err(|error| {
+            DeviceError::AttributeWriteInvalid(format!("Value does not match schema: {}", error))
+        })?;
+
+        self.handler.write(value)
+    }
+}
+
+/// Action with input, output and progress schemas derived from its types.
+pub struct TypedAction<In, Out, Progress = ()> {
+    handler: Box<dyn TypedActionHandler<In, Out, Progress>>,
+    input_schema: Schema,
+    output_schema: Schema,
+    progress_schema: Schema,
+    types: PhantomData<fn(In) -> (Out, Progress)>,
+}
+
+impl<In, Out, Progress> TypedAction<In, Out, Progress>
+where
+    In: AvroSchema,
+    Out: AvroSchema,
+    Progress: AvroSchema,
+{
+    /// Wrap a handler, fails when the schema of the input, output or progress
+    /// type is invalid.
+    pub fn new<H>(handler: H) -> Result<TypedAction<In, Out, Progress>, DeviceError>
+    where
+        H: TypedActionHandler<In, Out, Progress> + 'static,
+    {
+        Ok(TypedAction {
+            handler: Box::new(handler),
+            input_schema: In::avro_schema()?,
+            output_schema: Out::avro_schema()?,
+            progress_schema: Progress::avro_schema()?,
+            types: PhantomData,
+        })
+    }
+}
+
+impl<In, Out, Progress> fmt::Debug for TypedAction<In, Out, Progress> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("TypedAction")
+            .field("input_schema", &self.input_schema)
+            .field("output_schema", &self.output_schema)
+            .field("progress_schema", &self.progress_schema)
+            .finish()
+    }
+}
+
+impl<In, Out, Progress> Action for TypedAction<In, Out, Progress>
+where
+    In: DeserializeOwned,
+    Out: Serialize,
+    Progress: Serialize,
+{
+    fn input_schema(&self) -> Schema {
+        self.input_schema.clone()
+    }
+
+    fn output_schema(&self) -> Schema {
+        self.output_schema.clone()
+    }
+
+    fn progress_schema(&self) -> Schema {
+        self.progress_schema.clone()
+    }
+
+    fn concurrent(&self) -> bool {
+        self.handler.concurrent()
+    }
+
+    fn input_constraints(&self) -> Vec<Constraint> {
+        self.handler.input_constraints()
+    }
+
+    fn metadata(&self) -> Metadata {
+        self.handler.metadata()
+    }
+
+    fn run(&self, input: Value) -> Result<Value, DeviceError> {
+        self.run_job(input, &JobContext::new())
+    }
+
+    fn run_job(&self, input: Value, context: &JobContext) -> Result<Value, DeviceError> {
+        let input = decode_value::<In>(&input).map_err(|error| {
+            DeviceError::ActionRunInvalid(format!("Input does not match schema: {}", error))
+        })?;
+
+        let context = TypedJobContext::new(context.clone());
+        encode_value(self.handler.run_job(input, &context)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::{Arc, Mutex};
+
+    use serde::ser::{Error, Serializer};
+    use serde::{Deserialize, Deserializer};
+
+    /// Value that cannot be converted to Avro.
+    struct Unencodable;
+
+    impl AvroSchema for Unencodable {
+        fn avro_schema() -> Result<Schema, DeviceError> {
+            Ok(Schema::Null)
+        }
+    }
+
+    impl Serialize for Unencodable {
+        fn serialize<S: Serializer>(&self, _serializer: S) -> Result<S::Ok, S::Error> {
+            Err(S::Error::custom("unencodable"))
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Unencodable {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Unencodable, D::Error> {
+            <()>::deserialize(deserializer).map(|()| Unencodable)
+        }
+    }
+
+    /// Type without a valid schema.
+    #[derive(Debug)]
+    struct Invalid;
+
+    impl AvroSchema for Invalid {
+        fn avro_schema() -> Result<Schema, DeviceError> {
+            Err(DeviceError::InternalError("invalid schema".to_string()))
+        }
+    }
+
+    struct Counter;
+
+    impl TypedAttributeHandler<i64> for Counter {
+        fn readable(&self) -> bool {
+            true
+        }
+
+        fn writable(&self) -> bool {
+            true
+        }
+
+        fn read(&self) -> Result<i64, DeviceError> {
+            Ok(5)
+        }
+
+        fn write(&self, value: i64) -> Result<(), DeviceError> {
+            if value < 0 {
+                return Err(DeviceError::from("Counter cannot be negative"));
+            }
+
+            Ok(())
+        }
+    }
+
+    struct Broken;
+
+    impl TypedAttributeHandler<Unencodable> for Broken {
+        fn read(&self) -> Result<Unencodable, DeviceError> {
+            Ok(Unencodable)
+        }
+    }
+
+    impl TypedActionHandler<i64, Unencodable> for Broken {
+        fn run(&self, _input: i64) -> Result<Unencodable, DeviceError> {
+            Ok(Unencodable)
+        }
+    }
+
+    impl TypedActionHandler<i64, (), Unencodable> for Broken {
+        fn run(&self, _input: i64) -> Result<(), DeviceError> {
+            Ok(())
+        }
+
+        fn run_job(
+            &self,
+            _input: i64,
+            context: &TypedJobContext<Unencodable>,
+        ) -> Result<(), DeviceError> {
+            context.emit(Unencodable)
+        }
+    }
+
+    impl TypedActionHandler<i64, (), Invalid> for Broken {
+        fn run(&self, _input: i64) -> Result<(), DeviceError> {
+            Ok(())
+        }
+    }
+
+    impl TypedAttributeHandler<Invalid> for Broken {}
+
+    impl TypedActionHandler<Invalid, ()> for Broken {
+        fn run(&self, _input: Invalid) -> Result<(), DeviceError> {
+            Ok(())
+        }
+    }
+
+    struct Double;
+
+    impl TypedActionHandler<i64, i64> for Double {
+        fn run(&self, input: i64) -> Result<i64, DeviceError> {
+            input
+                .checked_mul(2)
+                .ok_or_else(|| DeviceError::from("Input is too large"))
+        }
+    }
+
+    struct Countdown;
+
+    impl TypedActionHandler<i64, String, i64> for Countdown {
+        fn run(&self, _input: i64) -> Result<String, DeviceError> {
+            Ok("done".to_string())
+        }
+
+        fn run_job(
+            &self,
+            input: i64,
+            context: &TypedJobContext<i64>,
+        ) -> Result<String, DeviceError> {
+            for remaining in (0..input).rev() {
+                context.emit(remaining)?;
+                context.set_progress((input - remaining) as f64 / input as f64);
+            }
+
+            self.run(input)
+        }
+    }
+
+    #[test]
+    fn encode_and_decode_values() {
+        assert_eq!(encode_value(5i64).unwrap(), Value::Long(5));
+        assert_eq!(decode_value::<i64>(&Value::Long(5)).unwrap(), 5);
+
+        match encode_value(Unencodable) {
+            Err(DeviceError::InternalError(_)) => {}
+            result => panic!("unexpected encode result: {:?}", result),
+        }
+
+        match decode_value::<i64>(&Value::String("five".to_string())) {
+            Err(DeviceError::InternalError(_)) => {}
+            result => panic!("unexpected decode result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn invalid_schemas() {
+        match TypedAttribute::<Invalid>::new(Broken) {
+            Err(DeviceError::InternalError(message)) => assert_eq!(message, "invalid schema"),
+            result => panic!("unexpected attribute: {:?}", result),
+        }
+
+        match TypedAction::<Invalid, ()>::new(Broken) {
+            Err(DeviceError::InternalError(message)) => assert_eq!(message, "invalid schema"),
+            result => panic!("unexpected action: {:?}", result),
+        }
+
+        match TypedAction::<i64, (), Invalid>::new(Broken) {
+            Err(DeviceError::InternalError(message)) => assert_eq!(message, "invalid schema"),
+            result => panic!("unexpected action: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn action_progress() {
+        let action = TypedAction::new(Countdown).unwrap();
+        assert_eq!(action.progress_schema(), Schema::Long);
+
+        let events = Arc::new(Mutex::new(vec![]));
+        let emitted = events.clone();
+        let context = JobContext::with_sink(move |event| emitted.lock().unwrap().push(event));
+        assert_eq!(
+            action.run_job(Value::Long(3), &context).unwrap(),
+            Value::String("done".to_string())
+        );
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            [Value::Long(2), Value::Long(1), Value::Long(0)]
+        );
+        assert_eq!(context.progress(), 1.0);
+
+        // actions without a progress type do not emit anything
+        let action = TypedAction::new(Double).unwrap();
+        assert_eq!(action.progress_schema(), Schema::Null);
+    }
+
+    #[test]
+    fn attribute_errors() {
+        let attribute = TypedAttribute::new(Counter).unwrap();
+        assert_eq!(attribute.schema(), Schema::Long);
+        assert_eq!(attribute.read().unwrap(), Value::Long(5));
+        attribute.write(Value::Long(1)).unwrap();
+
+        // values of the wrong type are rejected before reaching the handler
+        match attribute.write(Value::String("one".to_string())) {
+            Err(DeviceError::AttributeWriteInvalid(_)) => {}
+            result => panic!("unexpected write result: {:?}", result),
+        }
+
+        match attribute.write(Value::Long(-1)) {
+            Err(DeviceError::InternalError(message)) => {
+                assert_eq!(message, "Counter cannot be negative")
+            }
+            result => panic!("unexpected write result: {:?}", result),
+        }
+
+        let attribute = TypedAttribute::<Unencodable>::new(Broken).unwrap();
+        match attribute.read() {
+            Err(DeviceError::InternalError(_)) => {}
+            result => panic!("unexpected read result: {:?}", result),
+        }
+
+        match attribute.write(Value::Null) {
+            Err(DeviceError::NotImplemented) => {}
+            result => panic!("unexpected write result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn action_errors() {
+        let action = TypedAction::new(Double).unwrap();
+        assert_eq!(action.input_schema(), Schema::Long);
+        assert_eq!(action.output_schema(), Schema::Long);
+        assert_eq!(action.run(Value::Long(2)).unwrap(), Value::Long(4));
+
+        match action.run(Value::Boolean(true)) {
+            Err(DeviceError::ActionRunInvalid(_)) => {}
+            result => panic!("unexpected run result: {:?}", result),
+        }
+
+        match action.run(Value::Long(i64::MAX)) {
+            Err(DeviceError::InternalError(message)) => assert_eq!(message, "Input is too large"),
+            result => panic!("unexpected run result: {:?}", result),
+        }
+
+        let action = TypedAction::<i64, Unencodable>::new(Broken).unwrap();
+        match action.run(Value::Long(1)) {
+            Err(DeviceError::InternalError(_)) => {}
+            result => panic!("unexpected run result: {:?}", result),
+        }
+
+        let action = TypedAction::<i64, (), Unencodable>::new(Broken).unwrap();
+        match action.run_job(Value::Long(1), &JobContext::new()) {
+            Err(DeviceError::InternalError(_)) => {}
+            result => panic!("unexpected run result: {:?}", result),
+        }
+    }
+}
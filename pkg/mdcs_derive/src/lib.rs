@@ -0,0 +1,469 @@
+//! Declare MDCS devices from annotated Rust structs.
+//!
+//! Fields marked with `#[attribute(...)]` become attributes and methods of an
+//! impl block marked with `#[actions]` become actions. The struct is shared
+//! by every member, so the device state lives in one place:
+//!
+//! ```ignore
+//! use mdcs::device::{DeviceError, IntoDevice};
+//! use mdcs_derive::{actions, Device};
+//!
+//! #[derive(Device)]
+//! #[device(actions)]
+//! struct Fan {
+//!     #[attribute(read, write, unit = "rpm")]
+//!     speed: i32,
+//!     #[attribute(read, path = "status.running")]
+//!     running: bool,
+//! }
+//!
+//! #[actions]
+//! impl Fan {
+//!     #[action(description = "Stop the fan")]
+//!     fn stop(&mut self, _input: ()) -> Result<(), DeviceError> {
+//!         self.speed = 0;
+//!         self.running = false;
+//!         Ok(())
+//!     }
+//! }
+//!
+//! let device = Fan { speed: 0, running: false }.into_device()?;
+//! ```
+//!
+//! Attribute fields must implement `Clone` along with the serde traits and
+//! `mdcs::avro::AvroSchema`. Action methods take `&mut self` and a single
+//! input argument, and return `Result<Out, DeviceError>`. Long running
+//! actions take a `&TypedJobContext<Progress>` after the input to report
+//! progress, emit intermediate results of the `Progress` type and stop when
+//! cancelled.
+//!
+//! Devices without `#[device(actions)]` implement `DeviceActions` with no
+//! actions, so an `#[actions]` block for them fails to compile instead of
+//! being ignored:
+//!
+//! ```compile_fail
+//! use mdcs::device::DeviceError;
+//! use mdcs_derive::{actions, Device};
+//!
+//! #[derive(Device)]
+//! struct Fan {
+//!     #[attribute(read)]
+//!     speed: i32,
+//! }
+//!
+//! #[actions]
+//! impl Fan {
+//!     #[action]
+//!     fn stop(&mut self, _input: ()) -> Result<(), DeviceError> {
+//!         self.speed = 0;
+//!         Ok(())
+//!     }
+//! }
+//! ```
+//!
+//! Structs and enums used as attribute values or action inputs and outputs
+//! implement `mdcs::avro::AvroSchema` with `#[derive(AvroSchema)]`. Structs
+//! with named fields become records and enums with unit variants become Avro
+//! enums, both named after the type:
+//!
+//! ```ignore
+//! use mdcs_derive::AvroSchema;
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(AvroSchema, Serialize, Deserialize)]
+//! enum Mode {
+//!     Auto,
+//!     Manual,
+//! }
+//!
+//! #[derive(AvroSchema, Serialize, Deserialize)]
+//! struct Settings {
+//!     mode: Mode,
+//!     limit: Option<i32>,
+//! }
+//! ```
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    parse_macro_input, Attribute, Data, DeriveInput, Error, Fields, FnArg, ImplItem, ItemImpl, Lit,
+    Meta, NestedMeta,
+};
+
+/// Options given to an `#[attribute]` or `#[action]` annotation.
+#[derive(Default)]
+struct MemberOptions {
+    path: Option<String>,
+    description: Option<String>,
+    unit: Option<String>,
+    read: bool,
+    write: bool,
+}
+
+impl MemberOptions {
+    fn parse(attr: &Attribute, flags: &[&str], values: &[&str]) -> Result<MemberOptions, Error> {
+        let mut options = MemberOptions::default();
+        let nested = match attr.parse_meta()? {
+            Meta::Path(_) => return Ok(options),
+            Meta::List(list) => list.nested,
+            meta => return Err(Error::new_spanned(meta, "expected a list of options")),
+        };
+
+        for item in nested {
+            match item {
+                NestedMeta::Meta(Meta::Path(ref path)) => {
+                    let name = path_name(path);
+                    match name.as_str() {
+                        "read" if flags.contains(&"read") => options.read = true,
+                        "write" if flags.contains(&"write") => options.write = true,
+                        _ => return Err(Error::new_spanned(path, "unknown option")),
+                    }
+                }
+                NestedMeta::Meta(Meta::NameValue(ref pair)) => {
+                    let name = path_name(&pair.path);
+                    if !values.contains(&name.as_str()) {
+                        return Err(Error::new_spanned(&pair.path, "unknown option"));
+                    }
+
+                    let value = match pair.lit {
+                        Lit::Str(ref value) => value.value(),
+                        ref lit => return Err(Error::new_spanned(lit, "expected a string")),
+                    };
+
+                    match name.as_str() {
+                        "path" => options.path = Some(value),
+                        "description" => options.description = Some(value),
+                        "unit" => options.unit = Some(value),
+                        _ => unreachable!(),
+                    }
+                }
+                item => return Err(Error::new_spanned(item, "unknown option")),
+            }
+        }
+
+        Ok(options)
+    }
+
+    fn metadata(&self) -> TokenStream2 {
+        let description = optional_string(&self.description);
+        let unit = optional_string(&self.unit);
+
+        quote! {
+            ::mdcs::device::Metadata {
+                description: #description,
+                unit: #unit,
+                ..::mdcs::device::Metadata::default()
+            }
+        }
+    }
+}
+
+fn path_name(path: &syn::Path) -> String {
+    path.get_ident()
+        .map(ToString::to_string)
+        .unwrap_or_default()
+}
+
+fn optional_string(value: &Option<String>) -> TokenStream2 {
+    match value {
+        Some(value) => quote! { ::std::option::Option::Some(#value.to_string()) },
+        None => quote! { ::std::option::Option::None },
+    }
+}
+
+fn find_attr<'a>(attrs: &'a [Attribute], name: &str) -> Option<&'a Attribute> {
+    attrs.iter().find(|attr| attr.path.is_ident(name))
+}
+
+/// Implement `mdcs::device::IntoDevice` for a struct with annotated fields.
+#[proc_macro_derive(Device, attributes(device, attribute))]
+pub fn derive_device(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand_device(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+fn expand_device(input: &DeriveInput) -> Result<TokenStream2, Error> {
+    let name = &input.ident;
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &input.generics,
+            "devices cannot be generic",
+        ));
+    }
+
+    let fields = match input.data {
+        Data::Struct(ref data) => match data.fields {
+            Fields::Named(ref fields) => &fields.named,
+            _ => return Err(Error::new_spanned(input, "expected named fields")),
+        },
+        _ => return Err(Error::new_spanned(input, "expected a struct")),
+    };
+
+    // whether an `#[actions]` impl block registers actions as well
+    let mut has_actions = false;
+    if let Some(attr) = find_attr(&input.attrs, "device") {
+        match attr.parse_meta()? {
+            Meta::List(ref list) => {
+                for item in list.nested.iter() {
+                    match item {
+                        NestedMeta::Meta(Meta::Path(ref path)) if path.is_ident("actions") => {
+                            has_actions = true;
+                        }
+                        item => return Err(Error::new_spanned(item, "unknown option")),
+                    }
+                }
+            }
+            meta => return Err(Error::new_spanned(meta, "expected a list of options")),
+        }
+    }
+
+    let mut registrations: Vec<TokenStream2> = vec![];
+    for field in fields.iter() {
+        let attr = match find_attr(&field.attrs, "attribute") {
+            Some(attr) => attr,
+            None => continue,
+        };
+
+        let options =
+            MemberOptions::parse(attr, &["read", "write"], &["path", "description", "unit"])?;
+
+        if !options.read && !options.write {
+            return Err(Error::new_spanned(
+                attr,
+                "attributes must be readable, writable or both",
+            ));
+        }
+
+        let ident = field
+            .ident
+            .as_ref()
+            .expect("named field without identifier");
+
+        let ty = &field.ty;
+        let path = options.path.clone().unwrap_or_else(|| ident.to_string());
+        let read = options.read;
+        let write = options.write;
+        let metadata = options.metadata();
+
+        registrations.push(quote! {
+            {
+                fn field(state: &mut #name) -> &mut #ty {
+                    &mut state.#ident
+                }
+
+                let handler = ::mdcs::device::FieldAttribute::new(state.clone(), field)
+                    .readable(#read)
+                    .writable(#write)
+                    .with_metadata(#metadata);
+
+                let attribute = ::mdcs::device::TypedAttribute::new(handler)?;
+                device.insert(#path, ::mdcs::device::Member::Attribute(::std::boxed::Box::new(attribute)))?;
+            }
+        });
+    }
+
+    // conflicts with the implementation of an `#[actions]` block when the
+    // device was not told to register its actions
+    let actions = if has_actions {
+        quote! {}
+    } else {
+        quote! {
+            impl ::mdcs::device::DeviceActions for #name {
+                fn register_actions(
+                    _state: &::std::sync::Arc<::std::sync::Mutex<Self>>,
+                    _device: &::mdcs::device::Device,
+                ) -> ::std::result::Result<(), ::mdcs::device::DeviceError> {
+                    ::std::result::Result::Ok(())
+                }
+            }
+        }
+    };
+
+    Ok(quote! {
+        impl ::mdcs::device::IntoDevice for #name {
+            fn into_device(self) -> ::std::result::Result<::mdcs::device::Device, ::mdcs::device::DeviceError> {
+                let state = ::std::sync::Arc::new(::std::sync::Mutex::new(self));
+                let device = ::mdcs::device::Device::new();
+
+                #(#registrations)*
+                <#name as ::mdcs::device::DeviceActions>::register_actions(&state, &device)?;
+
+                ::std::result::Result::Ok(device)
+            }
+        }
+
+        #actions
+    })
+}
+
+/// Implement `mdcs::avro::AvroSchema` for a struct with named fields or an
+/// enum with unit variants.
+#[proc_macro_derive(AvroSchema)]
+pub fn derive_avro_schema(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand_avro_schema(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+fn expand_avro_schema(input: &DeriveInput) -> Result<TokenStream2, Error> {
+    let name = &input.ident;
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &input.generics,
+            "schema types cannot be generic",
+        ));
+    }
+
+    // the schema is named after the type like the avro-rs serde support
+    let schema_name = name.to_string();
+    let schema = match input.data {
+        Data::Struct(ref data) => {
+            let fields = match data.fields {
+                Fields::Named(ref fields) => &fields.named,
+                _ => return Err(Error::new_spanned(input, "expected named fields")),
+            };
+
+            let fields = fields.iter().map(|field| {
+                let ident = field
+                    .ident
+                    .as_ref()
+                    .expect("named field without identifier")
+                    .to_string();
+
+                let ty = &field.ty;
+                quote! {
+                    (#ident, <#ty as ::mdcs::avro::AvroSchema>::avro_schema()?)
+                }
+            });
+
+            quote! {
+                ::mdcs::avro::record_schema(#schema_name, ::std::vec![#(#fields),*])
+            }
+        }
+        Data::Enum(ref data) => {
+            let mut symbols: Vec<String> = vec![];
+            for variant in data.variants.iter() {
+                match variant.fields {
+                    Fields::Unit => symbols.push(variant.ident.to_string()),
+                    _ => {
+                        return Err(Error::new_spanned(
+                            variant,
+                            "enum variants cannot have fields",
+                        ));
+                    }
+                }
+            }
+
+            quote! {
+                ::mdcs::avro::enum_schema(#schema_name, &[#(#symbols),*])
+            }
+        }
+        _ => return Err(Error::new_spanned(input, "expected a struct or an enum")),
+    };
+
+    Ok(quote! {
+        impl ::mdcs::avro::AvroSchema for #name {
+            fn avro_schema() -> ::std::result::Result<::mdcs::avro::Schema, ::mdcs::device::DeviceError> {
+                #schema
+            }
+        }
+    })
+}
+
+/// Implement `mdcs::device::DeviceActions` for the methods of an impl block
+/// marked with `#[action]`.
+#[proc_macro_attribute]
+pub fn actions(_args: TokenStream, input: TokenStream) -> TokenStream {
+    let mut input = parse_macro_input!(input as ItemImpl);
+    match expand_actions(&mut input) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+fn expand_actions(input: &mut ItemImpl) -> Result<TokenStream2, Error> {
+    let mut registrations: Vec<TokenStream2> = vec![];
+
+    for item in input.items.iter_mut() {
+        let method = match item {
+            ImplItem::Method(method) => method,
+            _ => continue,
+        };
+
+        // the annotation is not a real attribute and has to be removed
+        let position = match method
+            .attrs
+            .iter()
+            .position(|attr| attr.path.is_ident("action"))
+        {
+            Some(position) => position,
+            None => continue,
+        };
+
+        let attr = method.attrs.remove(position);
+        let options = MemberOptions::parse(&attr, &[], &["path", "description"])?;
+
+        let mut inputs = method.sig.inputs.iter();
+        match inputs.next() {
+            Some(FnArg::Receiver(receiver))
+                if receiver.reference.is_some() && receiver.mutability.is_some() => {}
+            _ => {
+                return Err(Error::new_spanned(
+                    &method.sig,
+                    "actions must take `&mut self`",
+                ));
+            }
+        }
+
+        // long running actions also take the context of the job
+        let constructor = match inputs.count() {
+            1 => quote!(new),
+            2 => quote!(with_job),
+            _ => {
+                return Err(Error::new_spanned(
+                    &method.sig,
+                    "actions must take an input argument and optionally the job context",
+                ));
+            }
+        };
+
+        let ident = &method.sig.ident;
+        let path = options.path.clone().unwrap_or_else(|| ident.to_string());
+        let metadata = options.metadata();
+
+        registrations.push(quote! {
+            {
+                let handler = ::mdcs::device::MethodAction::#constructor(state.clone(), Self::#ident)
+                    .with_metadata(#metadata);
+
+                let action = ::mdcs::device::TypedAction::new(handler)?;
+                device.insert(#path, ::mdcs::device::Member::Action(::std::boxed::Box::new(action)))?;
+            }
+        });
+    }
+
+    let self_ty = &input.self_ty;
+    let (impl_generics, _type_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        #input
+
+        impl #impl_generics ::mdcs::device::DeviceActions for #self_ty #where_clause {
+            fn register_actions(
+                state: &::std::sync::Arc<::std::sync::Mutex<Self>>,
+                device: &::mdcs::device::Device,
+            ) -> ::std::result::Result<(), ::mdcs::device::DeviceError> {
+                #(#registrations)*
+                ::std::result::Result::Ok(())
+            }
+        }
+    })
+}
@@ -0,0 +1,311 @@
+//! Derive devices and schemas and use them through the `Device` interface.
+
+use avro_rs::types::Value;
+use serde::{Deserialize, Serialize};
+
+use mdcs::avro::{enum_schema, record_schema, AvroSchema, Schema};
+use std::sync::{Arc, Mutex};
+
+use mdcs::device::{Device, DeviceError, IntoDevice, JobContext, Member, TypedJobContext};
+use mdcs_derive::{actions, AvroSchema, Device};
+
+#[derive(AvroSchema, Clone, Debug, PartialEq, Serialize, Deserialize)]
+enum Mode {
+    Auto,
+    Manual,
+}
+
+#[derive(AvroSchema, Debug, Serialize, Deserialize)]
+struct Settings {
+    speed: i32,
+    running: bool,
+}
+
+#[derive(Device)]
+#[device(actions)]
+struct Fan {
+    #[attribute(read, write, unit = "rpm")]
+    speed: i32,
+    #[attribute(read, path = "status.running")]
+    running: bool,
+    #[attribute(read)]
+    mode: Mode,
+    // not a member of the device
+    starts: i64,
+}
+
+#[actions]
+impl Fan {
+    #[action(description = "Stop the fan")]
+    fn stop(&mut self, _input: ()) -> Result<(), DeviceError> {
+        self.speed = 0;
+        self.running = false;
+        Ok(())
+    }
+
+    #[action(path = "control.configure")]
+    fn configure(&mut self, settings: Settings) -> Result<i64, DeviceError> {
+        if settings.speed < 0 {
+            return Err(DeviceError::from("Speed cannot be negative"));
+        }
+
+        self.speed = settings.speed;
+        self.running = settings.running;
+        self.mode = Mode::Manual;
+        self.starts += 1;
+        Ok(self.starts)
+    }
+
+    #[action(path = "control.ramp")]
+    fn ramp(&mut self, target: i32, job: &TypedJobContext<i32>) -> Result<(), DeviceError> {
+        while self.speed < target {
+            if job.is_cancelled() {
+                return Err(DeviceError::from("Ramp cancelled"));
+            }
+
+            self.speed = (self.speed + 100).min(target);
+            job.emit(self.speed)?;
+        }
+
+        self.running = true;
+        Ok(())
+    }
+}
+
+#[derive(Device)]
+struct Sensor {
+    #[attribute(read)]
+    temperature: f64,
+}
+
+fn fan() -> Device {
+    let fan = Fan {
+        speed: 0,
+        running: false,
+        mode: Mode::Auto,
+        starts: 0,
+    };
+
+    fan.into_device().unwrap()
+}
+
+fn read(device: &Device, path: &str) -> Result<Value, DeviceError> {
+    match *device.get(path).expect("attribute not found") {
+        Member::Attribute(ref attribute) => attribute.read(),
+        ref member => panic!("{} is not an attribute: {:?}", path, member),
+    }
+}
+
+fn write(device: &Device, path: &str, value: Value) -> Result<(), DeviceError> {
+    match *device.get(path).expect("attribute not found") {
+        Member::Attribute(ref attribute) => attribute.write(value),
+        ref member => panic!("{} is not an attribute: {:?}", path, member),
+    }
+}
+
+fn run(device: &Device, path: &str, input: Value) -> Result<Value, DeviceError> {
+    match *device.get(path).expect("action not found") {
+        Member::Action(ref action) => action.run(input),
+        ref member => panic!("{} is not an action: {:?}", path, member),
+    }
+}
+
+fn run_job(
+    device: &Device,
+    path: &str,
+    input: Value,
+    context: &JobContext,
+) -> Result<Value, DeviceError> {
+    match *device.get(path).expect("action not found") {
+        Member::Action(ref action) => action.run_job(input, context),
+        ref member => panic!("{} is not an action: {:?}", path, member),
+    }
+}
+
+#[test]
+fn derive_schemas() {
+    assert_eq!(
+        Mode::avro_schema().unwrap(),
+        enum_schema("Mode", &["Auto", "Manual"]).unwrap()
+    );
+
+    assert_eq!(
+        Settings::avro_schema().unwrap(),
+        record_schema(
+            "Settings",
+            vec![("speed", Schema::Int), ("running", Schema::Boolean)]
+        )
+        .unwrap()
+    );
+}
+
+#[test]
+fn derive_members() {
+    let device = fan();
+    let paths: Vec<String> = device.iter().map(|(path, _member)| path).collect();
+
+    assert_eq!(
+        paths,
+        vec![
+            "control.configure",
+            "control.ramp",
+            "mode",
+            "speed",
+            "status.running",
+            "stop"
+        ]
+    );
+
+    match *device.get("speed").unwrap() {
+        Member::Attribute(ref attribute) => {
+            assert_eq!(attribute.schema(), Schema::Int);
+            assert!(attribute.readable());
+            assert!(attribute.writable());
+            assert_eq!(attribute.metadata().unit, Some("rpm".to_string()));
+        }
+        ref member => panic!("speed is not an attribute: {:?}", member),
+    }
+
+    match *device.get("status.running").unwrap() {
+        Member::Attribute(ref attribute) => {
+            assert_eq!(attribute.schema(), Schema::Boolean);
+            assert!(attribute.readable());
+            assert!(!attribute.writable());
+        }
+        ref member => panic!("status.running is not an attribute: {:?}", member),
+    }
+
+    match *device.get("stop").unwrap() {
+        Member::Action(ref action) => {
+            assert_eq!(action.input_schema(), Schema::Null);
+            assert_eq!(action.output_schema(), Schema::Null);
+            assert_eq!(
+                action.metadata().description,
+                Some("Stop the fan".to_string())
+            );
+        }
+        ref member => panic!("stop is not an action: {:?}", member),
+    }
+
+    match *device.get("control.ramp").unwrap() {
+        Member::Action(ref action) => {
+            assert_eq!(action.input_schema(), Schema::Int);
+            assert_eq!(action.progress_schema(), Schema::Int);
+        }
+        ref member => panic!("control.ramp is not an action: {:?}", member),
+    }
+}
+
+#[test]
+fn read_and_write_attributes() {
+    let device = fan();
+    assert_eq!(read(&device, "speed").unwrap(), Value::Int(0));
+    assert_eq!(
+        read(&device, "mode").unwrap(),
+        Value::Enum(0, "Auto".to_string())
+    );
+
+    write(&device, "speed", Value::Int(1200)).unwrap();
+    assert_eq!(read(&device, "speed").unwrap(), Value::Int(1200));
+
+    match write(&device, "speed", Value::String("fast".to_string())) {
+        Err(DeviceError::AttributeWriteInvalid(_)) => {}
+        result => panic!("unexpected write result: {:?}", result),
+    }
+
+    assert_eq!(read(&device, "speed").unwrap(), Value::Int(1200));
+}
+
+#[test]
+fn run_actions_on_shared_state() {
+    let device = fan();
+    let settings = Value::Record(vec![
+        ("speed".to_string(), Value::Int(800)),
+        ("running".to_string(), Value::Boolean(true)),
+    ]);
+
+    assert_eq!(
+        run(&device, "control.configure", settings.clone()).unwrap(),
+        Value::Long(1)
+    );
+
+    // actions and attributes share the fields of the struct
+    assert_eq!(read(&device, "speed").unwrap(), Value::Int(800));
+    assert_eq!(
+        read(&device, "status.running").unwrap(),
+        Value::Boolean(true)
+    );
+    assert_eq!(
+        read(&device, "mode").unwrap(),
+        Value::Enum(1, "Manual".to_string())
+    );
+
+    assert_eq!(
+        run(&device, "control.configure", settings).unwrap(),
+        Value::Long(2)
+    );
+
+    assert_eq!(run(&device, "stop", Value::Null).unwrap(), Value::Null);
+    assert_eq!(read(&device, "speed").unwrap(), Value::Int(0));
+    assert_eq!(
+        read(&device, "status.running").unwrap(),
+        Value::Boolean(false)
+    );
+}
+
+#[test]
+fn run_jobs_with_progress() {
+    let device = fan();
+    let events = Arc::new(Mutex::new(vec![]));
+    let emitted = events.clone();
+    let context = JobContext::with_sink(move |event| emitted.lock().unwrap().push(event));
+    assert_eq!(
+        run_job(&device, "control.ramp", Value::Int(250), &context).unwrap(),
+        Value::Null
+    );
+    assert_eq!(
+        *events.lock().unwrap(),
+        [Value::Int(100), Value::Int(200), Value::Int(250)]
+    );
+    assert_eq!(read(&device, "speed").unwrap(), Value::Int(250));
+
+    // the method observes cancellation through the typed context
+    context.cancel();
+    match run_job(&device, "control.ramp", Value::Int(500), &context) {
+        Err(DeviceError::InternalError(message)) => assert_eq!(message, "Ramp cancelled"),
+        result => panic!("unexpected run result: {:?}", result),
+    }
+}
+
+#[test]
+fn action_errors() {
+    let device = fan();
+    let negative = Value::Record(vec![
+        ("speed".to_string(), Value::Int(-1)),
+        ("running".to_string(), Value::Boolean(true)),
+    ]);
+
+    match run(&device, "control.configure", negative) {
+        Err(DeviceError::InternalError(message)) => {
+            assert_eq!(message, "Speed cannot be negative")
+        }
+        result => panic!("unexpected run result: {:?}", result),
+    }
+
+    match run(&device, "control.configure", Value::Int(1)) {
+        Err(DeviceError::ActionRunInvalid(_)) => {}
+        result => panic!("unexpected run result: {:?}", result),
+    }
+
+    // failed runs leave the state alone
+    assert_eq!(read(&device, "speed").unwrap(), Value::Int(0));
+}
+
+#[test]
+fn devices_without_actions() {
+    let device = Sensor { temperature: 21.5 }.into_device().unwrap();
+    let paths: Vec<String> = device.iter().map(|(path, _member)| path).collect();
+
+    assert_eq!(paths, vec!["temperature"]);
+    assert_eq!(read(&device, "temperature").unwrap(), Value::Double(21.5));
+}
@@ -1,4 +1,4 @@
-use mdcs_node::node::Config;
+use mdcs_node::node::{Config, Server};
 
 fn main() {
     let config = match Config::from_file("config.yaml") {
@@ -8,5 +8,12 @@ fn main() {
         }
     };
 
-    println!("{:#?}", config);
+    let server = match Server::new(&config) {
+        Ok(server) => server,
+        Err(error) => {
+            panic!("failed to start node: {}", error);
+        }
+    };
+
+    server.run().expect("Failed to run node server");
 }
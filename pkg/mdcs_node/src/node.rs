@@ -1,3 +1,15 @@
 pub mod config;
+pub mod descriptions;
+pub mod monitor;
+pub mod request;
+pub mod response;
+pub mod router;
+pub mod server;
+pub mod subscriptions;
 
 pub use config::*;
+pub use descriptions::Descriptions;
+pub use monitor::Monitor;
+pub use router::Router;
+pub use server::Server;
+pub use subscriptions::{Subscriber, Subscriptions};
@@ -5,21 +5,83 @@ use std::net::IpAddr;
 
 use serde::{Deserialize, Serialize};
 
+use crate::plugin::InstanceConfig;
+
+fn default_workers() -> usize {
+    16
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct NetworkConfig {
-    host: IpAddr,
-    port: u16,
+    pub host: IpAddr,
+    pub port: u16,
+
+    /// Number of requests processed at the same time across all clients.
+    #[serde(default = "default_workers")]
+    pub workers: usize,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PluginConfig {
     pub description: String,
-    pub command: String,
+
+    #[serde(flatten)]
+    pub instance: InstanceConfig,
+}
+
+/// Minimum change of a numeric value that is reported.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Deadband {
+    /// Change in the units of the value.
+    Absolute(f64),
+    /// Change relative to the last reported value, any change is reported
+    /// while the last reported value is zero.
+    Percent(f64),
+}
+
+/// Sample an attribute and only report it to subscribers when it changes.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MonitorConfig {
+    /// Sampling interval in milliseconds.
+    pub rate: u64,
+
+    /// Changes of numeric values smaller than this are not reported, values
+    /// of other types are reported whenever they change.
+    #[serde(default)]
+    pub deadband: Option<Deadband>,
+
+    /// Longest time in milliseconds without reporting the value even if it
+    /// has not changed.
+    #[serde(default)]
+    pub max_silence: Option<u64>,
+}
+
+impl MonitorConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.rate == 0 {
+            return Err("rate must be greater than zero".to_string());
+        }
+
+        match self.deadband {
+            Some(Deadband::Absolute(band)) | Some(Deadband::Percent(band))
+                if band.is_nan() || band < 0.0 =>
+            {
+                Err("deadband must not be negative".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DeviceConfig {
+    pub name: String,
     pub plugin: String,
+
+    /// Attributes sampled by the node, keyed by attribute path.
+    #[serde(default)]
+    pub monitors: HashMap<String, MonitorConfig>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
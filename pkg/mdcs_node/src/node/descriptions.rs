@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::plugin::response as presp;
+
+/// Description of a device and the plugin process it was retrieved from.
+#[derive(Debug)]
+struct Cached {
+    /// Number of times the plugin had been restarted.
+    restarts: u32,
+    /// Latest version of the device known to the node.
+    version: i64,
+    /// Description of the latest version unless it has yet to be retrieved.
+    description: Option<presp::Device>,
+}
+
+/// Latest description of each device, kept for plugins that report changes
+/// to their members.
+#[derive(Debug, Default)]
+pub struct Descriptions {
+    devices: Mutex<HashMap<String, Cached>>,
+}
+
+impl Descriptions {
+    pub fn new() -> Descriptions {
+        Descriptions::default()
+    }
+
+    /// Description of a device unless its plugin has been restarted or has
+    /// reported a change since it was retrieved.
+    pub fn get(&self, device: &str, restarts: u32) -> Option<presp::Device> {
+        let devices = self.devices.lock().unwrap();
+        devices
+            .get(device)
+            .filter(|cached| cached.restarts == restarts)
+            .and_then(|cached| cached.description.clone())
+    }
+
+    /// Remember the description of a device, it is ignored when a later
+    /// version from the same plugin process is already known.
+    pub fn store(&self, device: &str, restarts: u32, description: presp::Device) {
+        let mut devices = self.devices.lock().unwrap();
+        if let Some(cached) = devices.get(device) {
+            if cached.restarts == restarts && cached.version > description.version {
+                return;
+            }
+        }
+
+        devices.insert(
+            device.to_string(),
+            Cached {
+                restarts,
+                version: description.version,
+                description: Some(description),
+            },
+        );
+    }
+
+    /// Stop returning the description of a device once its plugin reports a
+    /// later version, until that version is stored.
+    pub fn invalidate(&self, device: &str, version: i64) {
+        let mut devices = self.devices.lock().unwrap();
+        if let Some(cached) = devices.get_mut(device) {
+            if cached.version < version {
+                cached.version = version;
+                cached.description = None;
+            }
+        }
+    }
+
+    pub fn forget(&self, device: &str) {
+        self.devices.lock().unwrap().remove(device);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn description(version: i64) -> presp::Device {
+        presp::Device {
+            attributes: vec![],
+            actions: vec![],
+            events: vec![],
+            identity: presp::Identity::default(),
+            version,
+        }
+    }
+
+    fn version(descriptions: &Descriptions, restarts: u32) -> Option<i64> {
+        descriptions
+            .get("device", restarts)
+            .map(|description| description.version)
+    }
+
+    #[test]
+    fn store_and_get() {
+        let descriptions = Descriptions::new();
+        assert_eq!(version(&descriptions, 0), None);
+
+        descriptions.store("device", 0, description(1));
+        assert_eq!(version(&descriptions, 0), Some(1));
+        assert!(descriptions.get("other", 0).is_none());
+
+        // descriptions from before a restart are not returned
+        assert_eq!(version(&descriptions, 1), None);
+
+        descriptions.forget("device");
+        assert_eq!(version(&descriptions, 0), None);
+    }
+
+    #[test]
+    fn older_versions_are_ignored() {
+        let descriptions = Descriptions::new();
+        descriptions.store("device", 0, description(2));
+        descriptions.store("device", 0, description(1));
+        assert_eq!(version(&descriptions, 0), Some(2));
+
+        // a restarted plugin counts its versions again
+        descriptions.store("device", 1, description(1));
+        assert_eq!(version(&descriptions, 1), Some(1));
+    }
+
+    #[test]
+    fn changes_invalidate_the_description() {
+        let descriptions = Descriptions::new();
+        descriptions.store("device", 0, description(1));
+
+        // changes already described keep the description
+        descriptions.invalidate("device", 1);
+        assert_eq!(version(&descriptions, 0), Some(1));
+
+        descriptions.invalidate("device", 3);
+        assert_eq!(version(&descriptions, 0), None);
+
+        // a description retrieved before the change arrives too late
+        descriptions.store("device", 0, description(2));
+        assert_eq!(version(&descriptions, 0), None);
+
+        descriptions.store("device", 0, description(3));
+        assert_eq!(version(&descriptions, 0), Some(3));
+
+        // devices without a description are unaffected
+        descriptions.invalidate("other", 1);
+        assert!(descriptions.get("other", 0).is_none());
+    }
+}
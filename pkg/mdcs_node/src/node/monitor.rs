@@ -0,0 +1,339 @@
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use avro_rs::types::Value;
+use avro_rs::{from_avro_datum, Schema};
+
+use crate::plugin::request as preq;
+use crate::plugin::response as presp;
+use crate::plugin::Instance;
+
+use super::config::{Deadband, MonitorConfig};
+use super::subscriptions::Subscriptions;
+
+/// Periodically samples an attribute and publishes the values that pass the
+/// change filter to its subscribers.
+#[derive(Debug)]
+pub struct Monitor {
+    stop: Option<Sender<()>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+/// Decides which samples are significant enough to report.
+#[derive(Debug)]
+struct ChangeFilter {
+    deadband: Option<Deadband>,
+    max_silence: Option<Duration>,
+    last: Option<(Vec<u8>, Option<f64>, Instant)>,
+}
+
+/// Value of numeric schemas used to apply the deadband.
+fn numeric(value: &Value) -> Option<f64> {
+    match *value {
+        Value::Int(value) => Some(f64::from(value)),
+        Value::Long(value) => Some(value as f64),
+        Value::Float(value) => Some(f64::from(value)),
+        Value::Double(value) => Some(value),
+        _ => None,
+    }
+}
+
+impl ChangeFilter {
+    fn new(config: &MonitorConfig) -> ChangeFilter {
+        ChangeFilter {
+            deadband: config.deadband,
+            max_silence: config.max_silence.map(Duration::from_millis),
+            last: None,
+        }
+    }
+
+    /// Whether a sample should be reported, samples are compared to the last
+    /// reported one. A percent deadband around a last reported value of zero
+    /// is zero wide, so every change away from zero is reported.
+    fn accept(&mut self, bytes: &[u8], number: Option<f64>, now: Instant) -> bool {
+        let changed = match self.last {
+            None => true,
+            Some((_, _, reported)) if self.silence_exceeded(reported, now) => true,
+            Some((ref last_bytes, last_number, _)) => match (last_number, number, self.deadband) {
+                (Some(last), Some(number), Some(Deadband::Absolute(band))) => {
+                    (number - last).abs() > band
+                }
+                (Some(last), Some(number), Some(Deadband::Percent(band))) => {
+                    (number - last).abs() > last.abs() * band / 100.0
+                }
+                _ => last_bytes[..] != *bytes,
+            },
+        };
+
+        if changed {
+            self.last = Some((bytes.to_vec(), number, now));
+        }
+
+        changed
+    }
+
+    fn silence_exceeded(&self, reported: Instant, now: Instant) -> bool {
+        match self.max_silence {
+            Some(max_silence) => now.duration_since(reported) >= max_silence,
+            None => false,
+        }
+    }
+}
+
+struct Sampler {
+    device: String,
+    attribute: String,
+    instance: Arc<Instance>,
+    subscriptions: Arc<Subscriptions>,
+    filter: ChangeFilter,
+    schema: Option<Schema>,
+    failing: bool,
+}
+
+impl Sampler {
+    /// Retrieve the attribute schema from the plugin to decode samples.
+    fn describe(&self) -> Option<Schema> {
+        let request = preq::Request::DescribeDevice(preq::DescribeDevice {
+            path: Some(self.attribute.clone()),
+        });
+        let device = match self.instance.process_request(request) {
+            Ok(presp::Response::Device(device)) => device,
+            _ => return None,
+        };
+
+        device
+            .attributes
+            .into_iter()
+            .find(|attribute| attribute.path == self.attribute)
+            .and_then(|attribute| Schema::parse_str(&attribute.schema).ok())
+    }
+
+    fn sample(&mut self) {
+        let request = preq::Request::ReadAttribute(preq::ReadAttribute {
+            path: self.attribute.clone(),
+        });
+
+        let value = match self.instance.process_request(request) {
+            Ok(presp::Response::AttributeValue(value)) => value,
+            Ok(response) => return self.fail(format!("{:?}", response)),
+            Err(error) => return self.fail(format!("{}", error)),
+        };
+
+        // a sample that cannot be decoded cannot be compared to the deadband
+        let number = match self.filter.deadband {
+            Some(_) => match self.decode(&value.value) {
+                Ok(number) => number,
+                Err(message) => return self.fail(message),
+            },
+            None => None,
+        };
+
+        if self.failing {
+            eprintln!("Monitor {} {} recovered", self.device, self.attribute);
+            self.failing = false;
+        }
+
+        if self.filter.accept(&value.value, number, Instant::now()) {
+            let update = presp::AttributeUpdate {
+                path: self.attribute.clone(),
+                value: value.value,
+                time: value.time,
+            };
+
+            self.subscriptions
+                .publish(&self.device, presp::Response::AttributeUpdate(update));
+        }
+    }
+
+    /// Decode the numeric value of a sample, the attribute is described again
+    /// when its schema may have changed because the plugin was replaced.
+    fn decode(&mut self, bytes: &[u8]) -> Result<Option<f64>, String> {
+        for _ in 0..2 {
+            if self.schema.is_none() {
+                self.schema = self.describe();
+            }
+
+            let schema = self
+                .schema
+                .as_ref()
+                .ok_or_else(|| "failed to describe attribute".to_string())?;
+
+            match from_avro_datum(schema, &mut &bytes[..], None) {
+                Ok(decoded) => return Ok(numeric(&decoded)),
+                Err(_) => self.schema = None,
+            }
+        }
+
+        Err("failed to decode attribute value".to_string())
+    }
+
+    /// Log sampling failures once until the attribute can be read again.
+    fn fail(&mut self, message: String) {
+        if !self.failing {
+            eprintln!(
+                "Monitor {} {} failed to sample attribute: {}",
+                self.device, self.attribute, message
+            );
+
+            self.failing = true;
+        }
+    }
+
+    fn run(&mut self, rate: Duration, stop: Receiver<()>) {
+        loop {
+            self.sample();
+
+            match stop.recv_timeout(rate) {
+                Err(RecvTimeoutError::Timeout) => continue,
+                _ => break,
+            }
+        }
+    }
+}
+
+impl Monitor {
+    pub fn start(
+        device: &str,
+        attribute: &str,
+        config: &MonitorConfig,
+        instance: Arc<Instance>,
+        subscriptions: Arc<Subscriptions>,
+    ) -> Monitor {
+        let mut sampler = Sampler {
+            device: device.to_string(),
+            attribute: attribute.to_string(),
+            instance,
+            subscriptions,
+            filter: ChangeFilter::new(config),
+            schema: None,
+            failing: false,
+        };
+
+        let rate = Duration::from_millis(config.rate);
+        let (stop, receiver) = channel();
+        let handle = thread::spawn(move || sampler.run(rate, receiver));
+
+        Monitor {
+            stop: Some(stop),
+            handle: Some(handle),
+        }
+    }
+}
+
+impl Drop for Monitor {
+    fn drop(&mut self) {
+        // closing the channel wakes the sampler up
+        self.stop.take();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(deadband: Option<Deadband>, max_silence: Option<u64>) -> ChangeFilter {
+        ChangeFilter::new(&MonitorConfig {
+            rate: 100,
+            deadband,
+            max_silence,
+        })
+    }
+
+    fn sample(filter: &mut ChangeFilter, number: f64, now: Instant) -> bool {
+        filter.accept(&number.to_le_bytes(), Some(number), now)
+    }
+
+    #[test]
+    fn first_sample_is_reported() {
+        let mut filter = filter(Some(Deadband::Absolute(100.0)), None);
+        assert!(sample(&mut filter, 1.0, Instant::now()));
+    }
+
+    #[test]
+    fn absolute_deadband() {
+        let mut filter = filter(Some(Deadband::Absolute(0.5)), None);
+        let now = Instant::now();
+
+        assert!(sample(&mut filter, 10.0, now));
+        assert!(!sample(&mut filter, 10.4, now));
+        assert!(!sample(&mut filter, 9.5, now));
+
+        // compared with the last reported value rather than the last sample
+        assert!(sample(&mut filter, 10.6, now));
+        assert!(!sample(&mut filter, 10.2, now));
+        assert!(sample(&mut filter, 10.0, now));
+    }
+
+    #[test]
+    fn percent_deadband() {
+        let mut filter = filter(Some(Deadband::Percent(10.0)), None);
+        let now = Instant::now();
+
+        assert!(sample(&mut filter, 100.0, now));
+        assert!(!sample(&mut filter, 105.0, now));
+        assert!(!sample(&mut filter, 90.0, now));
+        assert!(sample(&mut filter, 111.0, now));
+        assert!(sample(&mut filter, -50.0, now));
+        assert!(!sample(&mut filter, -54.0, now));
+    }
+
+    #[test]
+    fn percent_deadband_around_zero() {
+        let mut filter = filter(Some(Deadband::Percent(10.0)), None);
+        let now = Instant::now();
+
+        assert!(sample(&mut filter, 0.0, now));
+        assert!(!sample(&mut filter, 0.0, now));
+        assert!(sample(&mut filter, 0.001, now));
+    }
+
+    #[test]
+    fn max_silence() {
+        let mut filter = filter(Some(Deadband::Absolute(1.0)), Some(1000));
+        let start = Instant::now();
+
+        assert!(sample(&mut filter, 5.0, start));
+        assert!(!sample(
+            &mut filter,
+            5.0,
+            start + Duration::from_millis(999)
+        ));
+        assert!(sample(
+            &mut filter,
+            5.0,
+            start + Duration::from_millis(1000)
+        ));
+
+        // the silence starts over with every reported value
+        assert!(!sample(
+            &mut filter,
+            5.5,
+            start + Duration::from_millis(1999)
+        ));
+        assert!(sample(
+            &mut filter,
+            5.5,
+            start + Duration::from_millis(2000)
+        ));
+    }
+
+    #[test]
+    fn values_without_deadband() {
+        let mut filter = filter(None, None);
+        let now = Instant::now();
+
+        assert!(filter.accept(b"on", None, now));
+        assert!(!filter.accept(b"on", None, now));
+        assert!(filter.accept(b"off", None, now));
+
+        // numeric values are reported on any change
+        assert!(sample(&mut filter, 1.0, now));
+        assert!(!sample(&mut filter, 1.0, now));
+        assert!(sample(&mut filter, 1.000_001, now));
+    }
+}
@@ -0,0 +1,126 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AttributeIdentifier {
+    pub device: String,
+    pub attribute: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ActionIdentifier {
+    pub device: String,
+    pub action: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EventIdentifier {
+    pub device: String,
+    pub event: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JobIdentifier {
+    pub device: String,
+    pub job: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AttributeValue {
+    pub value: Vec<u8>,
+    pub time: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ActionInput {
+    pub value: Vec<u8>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Read {
+    pub target: AttributeIdentifier,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Write {
+    pub target: AttributeIdentifier,
+    pub data: AttributeValue,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Run {
+    pub target: ActionIdentifier,
+    pub data: ActionInput,
+    #[serde(default)]
+    pub background: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Health {}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Inventory {}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JobStatus {
+    pub target: JobIdentifier,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JobResult {
+    pub target: JobIdentifier,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CancelJob {
+    pub target: JobIdentifier,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Subscribe {
+    pub target: AttributeIdentifier,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Unsubscribe {
+    pub target: AttributeIdentifier,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SubscribeEvent {
+    pub target: EventIdentifier,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UnsubscribeEvent {
+    pub target: EventIdentifier,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Describe {
+    pub device: String,
+    #[serde(default)]
+    pub path: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Request {
+    Read(Read),
+    Write(Write),
+    Run(Run),
+    Health(Health),
+    Inventory(Inventory),
+    JobStatus(JobStatus),
+    JobResult(JobResult),
+    CancelJob(CancelJob),
+    Subscribe(Subscribe),
+    Unsubscribe(Unsubscribe),
+    SubscribeEvent(SubscribeEvent),
+    UnsubscribeEvent(UnsubscribeEvent),
+    Describe(Describe),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RequestMessage {
+    pub id: i64,
+    pub request: Request,
+}
@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AttributeValue {
+    pub value: Vec<u8>,
+    pub time: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ActionOutput {
+    pub value: Vec<u8>,
+    pub time: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ServerError {
+    pub message: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AttributeError {
+    pub message: String,
+    pub device: String,
+    pub attribute: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ActionError {
+    pub message: String,
+    pub device: String,
+    pub action: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum DeviceState {
+    Starting,
+    Running,
+    Unhealthy,
+    BackingOff,
+    Failed,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeviceHealth {
+    pub device: String,
+    pub state: DeviceState,
+    pub missed_heartbeats: i32,
+    pub restarts: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Health {
+    pub devices: Vec<DeviceHealth>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum JobState {
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Job {
+    pub device: String,
+    pub job: i64,
+    pub action: String,
+    pub state: JobState,
+    pub progress: f64,
+    pub message: Option<String>,
+    pub event: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JobError {
+    pub message: String,
+    pub device: String,
+    pub job: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ActionProgress {
+    pub device: String,
+    pub action: String,
+    pub value: Vec<u8>,
+    pub time: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Subscription {
+    pub device: String,
+    pub attribute: String,
+    pub active: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AttributeUpdate {
+    pub device: String,
+    pub attribute: String,
+    pub value: Vec<u8>,
+    pub time: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EventError {
+    pub message: String,
+    pub device: String,
+    pub event: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EventSubscription {
+    pub device: String,
+    pub event: String,
+    pub active: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Event {
+    pub device: String,
+    pub event: String,
+    pub payload: Vec<u8>,
+    pub time: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Metadata {
+    pub description: Option<String>,
+    pub unit: Option<String>,
+    pub minimum: Option<f64>,
+    pub maximum: Option<f64>,
+    pub step: Option<f64>,
+    pub labels: HashMap<String, String>,
+    pub precision: Option<i32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AttributeDescription {
+    pub path: String,
+    pub flags: Vec<String>,
+    pub schema: String,
+    pub metadata: Metadata,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ActionDescription {
+    pub path: String,
+    pub input_schema: String,
+    pub output_schema: String,
+    pub progress_schema: String,
+    pub metadata: Metadata,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EventDescription {
+    pub path: String,
+    pub schema: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Identity {
+    pub name: Option<String>,
+    pub vendor: Option<String>,
+    pub model: Option<String>,
+    pub serial: Option<String>,
+    pub firmware_version: Option<String>,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeviceDescription {
+    pub device: String,
+    pub attributes: Vec<AttributeDescription>,
+    pub actions: Vec<ActionDescription>,
+    pub events: Vec<EventDescription>,
+    pub identity: Identity,
+    pub version: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConstraintViolation {
+    pub message: String,
+    pub device: String,
+    pub member: String,
+    pub field: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeviceChanged {
+    pub device: String,
+    pub version: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeviceInventory {
+    pub device: String,
+    pub plugin: Option<String>,
+    pub plugin_version: Option<String>,
+    pub state: DeviceState,
+    pub identity: Option<Identity>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Inventory {
+    pub devices: Vec<DeviceInventory>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Response {
+    AttributeValue(AttributeValue),
+    ActionOutput(ActionOutput),
+    ServerError(ServerError),
+    AttributeError(AttributeError),
+    ActionError(ActionError),
+    Health(Health),
+    Job(Job),
+    JobError(JobError),
+    ActionProgress(ActionProgress),
+    Subscription(Subscription),
+    AttributeUpdate(AttributeUpdate),
+    EventError(EventError),
+    EventSubscription(EventSubscription),
+    Event(Event),
+    DeviceDescription(Box<DeviceDescription>),
+    ConstraintViolation(ConstraintViolation),
+    DeviceChanged(DeviceChanged),
+    Inventory(Inventory),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResponseMessage {
+    pub id: i64,
+    pub response: Response,
+}
@@ -0,0 +1,864 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex, PoisonError, Weak};
+use std::thread;
+
+use crate::plugin::request as preq;
+use crate::plugin::response as presp;
+use crate::plugin::{
+    Instance, InstanceError, InstanceState, CAPABILITY_CANCELLATION, CAPABILITY_DEVICE_CHANGES,
+    CAPABILITY_SUBSCRIPTIONS,
+};
+
+use super::config::Config;
+use super::descriptions::Descriptions;
+use super::monitor::Monitor;
+use super::request::{self as req, Request};
+use super::response::ResponseMessage;
+use super::response::{self as resp, Response};
+use super::subscriptions::{Subscriber, Subscriptions};
+
+/// Locks of device members keyed by device name and member path.
+type MemberLocks = HashMap<(String, String), Arc<Mutex<()>>>;
+
+/// Routes node requests to the plugin instance responsible for each device.
+#[derive(Debug)]
+pub struct Router {
+    devices: HashMap<String, Arc<Instance>>,
+    subscriptions: Arc<Subscriptions>,
+    descriptions: Arc<Descriptions>,
+    monitors: HashMap<(String, String), Monitor>,
+
+    /// Held while the plugin side subscription of a member changes.
+    pending: Mutex<MemberLocks>,
+}
+
+fn server_error(message: String) -> Response {
+    Response::ServerError(resp::ServerError { message })
+}
+
+fn instance_error(device: &str, error: InstanceError) -> Response {
+    server_error(format!("Device {}: {}", device, error))
+}
+
+fn unexpected_response(device: &str, response: presp::Response) -> Response {
+    server_error(format!(
+        "Device {}: unexpected plugin response: {:?}",
+        device, response
+    ))
+}
+
+fn violation_response(device: &str, violation: presp::Violation) -> Response {
+    Response::ConstraintViolation(resp::ConstraintViolation {
+        message: violation.message,
+        device: device.to_string(),
+        member: violation.path,
+        field: violation.field,
+    })
+}
+
+fn job_response(device: &str, job: presp::Job) -> Response {
+    let state = match job.state {
+        presp::JobState::Running => resp::JobState::Running,
+        presp::JobState::Completed => resp::JobState::Completed,
+        presp::JobState::Failed => resp::JobState::Failed,
+        presp::JobState::Cancelled => resp::JobState::Cancelled,
+    };
+
+    Response::Job(resp::Job {
+        device: device.to_string(),
+        job: job.job,
+        action: job.path,
+        state,
+        progress: job.progress,
+        message: job.message,
+        event: job.event,
+    })
+}
+
+fn metadata(metadata: presp::Metadata) -> resp::Metadata {
+    resp::Metadata {
+        description: metadata.description,
+        unit: metadata.unit,
+        minimum: metadata.minimum,
+        maximum: metadata.maximum,
+        step: metadata.step,
+        labels: metadata.labels,
+        precision: metadata.precision,
+    }
+}
+
+fn device_description(device: &str, description: presp::Device) -> Response {
+    let attributes = description
+        .attributes
+        .into_iter()
+        .map(|attribute| resp::AttributeDescription {
+            path: attribute.path,
+            flags: attribute.flags,
+            schema: attribute.schema,
+            metadata: metadata(attribute.metadata),
+        })
+        .collect();
+
+    let actions = description
+        .actions
+        .into_iter()
+        .map(|action| resp::ActionDescription {
+            path: action.path,
+            input_schema: action.input_schema,
+            output_schema: action.output_schema,
+            progress_schema: action.progress_schema,
+            metadata: metadata(action.metadata),
+        })
+        .collect();
+
+    let events = description
+        .events
+        .into_iter()
+        .map(|event| resp::EventDescription {
+            path: event.path,
+            schema: event.schema,
+        })
+        .collect();
+
+    Response::DeviceDescription(Box::new(resp::DeviceDescription {
+        device: device.to_string(),
+        attributes,
+        actions,
+        events,
+        identity: identity(description.identity),
+        version: description.version,
+    }))
+}
+
+fn identity(identity: presp::Identity) -> resp::Identity {
+    resp::Identity {
+        name: identity.name,
+        vendor: identity.vendor,
+        model: identity.model,
+        serial: identity.serial,
+        firmware_version: identity.firmware_version,
+        description: identity.description,
+    }
+}
+
+fn device_state(state: InstanceState) -> resp::DeviceState {
+    match state {
+        InstanceState::Starting => resp::DeviceState::Starting,
+        InstanceState::Running => resp::DeviceState::Running,
+        InstanceState::Unhealthy => resp::DeviceState::Unhealthy,
+        InstanceState::BackingOff => resp::DeviceState::BackingOff,
+        InstanceState::Failed => resp::DeviceState::Failed,
+    }
+}
+
+/// Whether a plugin reports changes to the members of its device, so their
+/// description can be kept.
+fn reports_changes(instance: &Instance) -> bool {
+    instance
+        .info()
+        .map(|info| {
+            info.capabilities
+                .iter()
+                .any(|c| c == CAPABILITY_DEVICE_CHANGES)
+        })
+        .unwrap_or(false)
+}
+
+/// Ask a plugin to describe its device, or only the members at or below a
+/// path. Descriptions of the whole device are kept when the plugin reports
+/// its changes.
+fn fetch_description(
+    device: &str,
+    path: Option<String>,
+    instance: &Instance,
+    descriptions: &Descriptions,
+) -> Result<presp::Device, Response> {
+    // a description retrieved from a restarted plugin replaces the kept one
+    let restarts = instance.status().restarts;
+    let request = preq::Request::DescribeDevice(preq::DescribeDevice { path: path.clone() });
+
+    match instance.process_request(request) {
+        Ok(presp::Response::Device(description)) => {
+            if path.is_none() && reports_changes(instance) {
+                descriptions.store(device, restarts, (*description).clone());
+            }
+
+            Ok(*description)
+        }
+        Ok(presp::Response::Error(error)) => Err(server_error(format!(
+            "Device {}: {}",
+            device, error.message
+        ))),
+        Ok(response) => Err(unexpected_response(device, response)),
+        Err(error) => Err(instance_error(device, error)),
+    }
+}
+
+/// Describe a device again after its plugin reported a change and tell the
+/// clients about it.
+fn refresh(
+    device: &str,
+    version: i64,
+    instance: &Instance,
+    descriptions: &Descriptions,
+    subscriptions: &Subscriptions,
+) {
+    if let Err(error) = fetch_description(device, None, instance, descriptions) {
+        eprintln!("Failed to refresh device {}: {:?}", device, error);
+        descriptions.forget(device);
+    }
+
+    subscriptions.device_changed(device, version);
+}
+
+/// Describes devices again after their plugins report changes on a single
+/// thread, changes reported while a device waits for its refresh are handled
+/// by the same refresh.
+#[derive(Debug)]
+struct Refresher {
+    /// Latest reported version of each device waiting for a refresh.
+    pending: Arc<Mutex<HashMap<String, i64>>>,
+    queue: Mutex<Sender<(String, Weak<Instance>)>>,
+}
+
+impl Refresher {
+    fn start(descriptions: Arc<Descriptions>, subscriptions: Arc<Subscriptions>) -> Refresher {
+        let pending = Arc::new(Mutex::new(HashMap::new()));
+        let (queue, receiver) = channel::<(String, Weak<Instance>)>();
+
+        // stops once every device handler has been dropped
+        let refresh_pending = pending.clone();
+        thread::spawn(move || {
+            for (device, instance) in receiver {
+                let version = refresh_pending
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .remove(&device);
+
+                if let (Some(version), Some(instance)) = (version, instance.upgrade()) {
+                    refresh(&device, version, &instance, &descriptions, &subscriptions);
+                }
+            }
+        });
+
+        Refresher {
+            pending,
+            queue: Mutex::new(queue),
+        }
+    }
+
+    /// Schedule a refresh of a device unless one is already waiting.
+    fn changed(&self, device: &str, version: i64, instance: Weak<Instance>) {
+        let mut pending = self.pending.lock().unwrap_or_else(PoisonError::into_inner);
+
+        if let Some(latest) = pending.get_mut(device) {
+            *latest = (*latest).max(version);
+            return;
+        }
+
+        pending.insert(device.to_string(), version);
+        let _ = self
+            .queue
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .send((device.to_string(), instance));
+    }
+}
+
+impl Router {
+    pub fn new(config: &Config) -> Result<Router, Box<dyn Error>> {
+        let mut devices = HashMap::new();
+        let subscriptions = Arc::new(Subscriptions::new());
+        let descriptions = Arc::new(Descriptions::new());
+        let refresher = Arc::new(Refresher::start(
+            descriptions.clone(),
+            subscriptions.clone(),
+        ));
+        let mut monitors = HashMap::new();
+
+        for device in &config.devices {
+            if devices.contains_key(&device.name) {
+                return Err(format!("Duplicate device name: {}", device.name).into());
+            }
+
+            let plugin = config.plugins.get(&device.plugin).ok_or_else(|| {
+                format!(
+                    "Device {} refers to unknown plugin: {}",
+                    device.name, device.plugin
+                )
+            })?;
+
+            let instance = Instance::new(plugin.instance.clone()).map_err(|error| {
+                format!(
+                    "Failed to start plugin {} for device {}: {}",
+                    device.plugin, device.name, error
+                )
+            })?;
+
+            // forward attribute updates and events to the subscribed clients
+            // and describe the device again when its members change
+            let instance = Arc::new(instance);
+            let device_instance = Arc::downgrade(&instance);
+            let device_subscriptions = subscriptions.clone();
+            let device_descriptions = descriptions.clone();
+            let device_refresher = refresher.clone();
+            let device_name = device.name.clone();
+            instance.set_update_handler(move |update| match update {
+                presp::Response::DeviceChanged(changed) => {
+                    // the plugin cannot be asked while its update is handled
+                    device_descriptions.invalidate(&device_name, changed.version);
+                    device_refresher.changed(
+                        &device_name,
+                        changed.version,
+                        Weak::clone(&device_instance),
+                    );
+                }
+                update => device_subscriptions.publish(&device_name, update),
+            });
+
+            // sample monitored attributes and publish their changes instead
+            for (attribute, monitor) in device.monitors.iter() {
+                monitor.validate().map_err(|error| {
+                    format!(
+                        "Invalid monitor for device {} attribute {}: {}",
+                        device.name, attribute, error
+                    )
+                })?;
+
+                let key = (device.name.clone(), attribute.clone());
+                let monitor = Monitor::start(
+                    &device.name,
+                    attribute,
+                    monitor,
+                    instance.clone(),
+                    subscriptions.clone(),
+                );
+
+                monitors.insert(key, monitor);
+            }
+
+            devices.insert(device.name.clone(), instance);
+        }
+
+        Ok(Router {
+            devices,
+            subscriptions,
+            descriptions,
+            monitors,
+            pending: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn describe(&self, args: &req::Describe) -> Response {
+        // retrieve the device plugin instance
+        let instance = match self.devices.get(&args.device) {
+            Some(instance) => instance,
+            None => return server_error(format!("Device not found: {}", args.device)),
+        };
+
+        let description = match args.path {
+            Some(ref path) => fetch_description(
+                &args.device,
+                Some(path.clone()),
+                instance,
+                &self.descriptions,
+            ),
+            None => self.whole_description(&args.device, instance),
+        };
+
+        match description {
+            Ok(description) => device_description(&args.device, description),
+            Err(error) => error,
+        }
+    }
+
+    /// Describe the whole device from the kept description if the plugin has
+    /// not reported a change since, or ask the plugin otherwise.
+    fn whole_description(
+        &self,
+        device: &str,
+        instance: &Instance,
+    ) -> Result<presp::Device, Response> {
+        if reports_changes(instance) {
+            let restarts = instance.status().restarts;
+            if let Some(description) = self.descriptions.get(device, restarts) {
+                return Ok(description);
+            }
+        }
+
+        fetch_description(device, None, instance, &self.descriptions)
+    }
+
+    fn read(&self, args: &req::Read) -> Response {
+        let target = &args.target;
+        let attribute_error = |message: String| {
+            Response::AttributeError(resp::AttributeError {
+                message,
+                device: target.device.clone(),
+                attribute: target.attribute.clone(),
+            })
+        };
+
+        // retrieve the device plugin instance
+        let instance = match self.devices.get(&target.device) {
+            Some(instance) => instance,
+            None => return attribute_error("Device not found".to_string()),
+        };
+
+        // read the attribute value
+        let request = preq::Request::ReadAttribute(preq::ReadAttribute {
+            path: target.attribute.clone(),
+        });
+
+        match instance.process_request(request) {
+            Ok(presp::Response::AttributeValue(value)) => {
+                Response::AttributeValue(resp::AttributeValue {
+                    value: value.value,
+                    time: value.time,
+                })
+            }
+            Ok(presp::Response::Error(error)) => attribute_error(error.message),
+            Ok(response) => unexpected_response(&target.device, response),
+            Err(error) => instance_error(&target.device, error),
+        }
+    }
+
+    fn write(&self, args: &req::Write) -> Response {
+        let target = &args.target;
+        let attribute_error = |message: String| {
+            Response::AttributeError(resp::AttributeError {
+                message,
+                device: target.device.clone(),
+                attribute: target.attribute.clone(),
+            })
+        };
+
+        // retrieve the device plugin instance
+        let instance = match self.devices.get(&target.device) {
+            Some(instance) => instance,
+            None => return attribute_error("Device not found".to_string()),
+        };
+
+        // write the attribute value
+        let request = preq::Request::WriteAttribute(preq::WriteAttribute {
+            path: target.attribute.clone(),
+            value: args.data.value.clone(),
+        });
+
+        match instance.process_request(request) {
+            Ok(presp::Response::AttributeValue(value)) => {
+                Response::AttributeValue(resp::AttributeValue {
+                    value: value.value,
+                    time: value.time,
+                })
+            }
+            Ok(presp::Response::Error(error)) => attribute_error(error.message),
+            Ok(presp::Response::Violation(violation)) => {
+                violation_response(&target.device, violation)
+            }
+            Ok(response) => unexpected_response(&target.device, response),
+            Err(error) => instance_error(&target.device, error),
+        }
+    }
+
+    fn run<F>(&self, args: &req::Run, mut progress: F) -> Response
+    where
+        F: FnMut(Response),
+    {
+        let target = &args.target;
+        let action_error = |message: String| {
+            Response::ActionError(resp::ActionError {
+                message,
+                device: target.device.clone(),
+                action: target.action.clone(),
+            })
+        };
+
+        // retrieve the device plugin instance
+        let instance = match self.devices.get(&target.device) {
+            Some(instance) => instance,
+            None => return action_error("Device not found".to_string()),
+        };
+
+        // run the action
+        let request = preq::Request::RunAction(preq::RunAction {
+            path: target.action.clone(),
+            input: args.data.value.clone(),
+            background: args.background,
+        });
+
+        let result = instance.process_request_with_progress(request, |event| {
+            progress(Response::ActionProgress(resp::ActionProgress {
+                device: target.device.clone(),
+                action: target.action.clone(),
+                value: event.value,
+                time: event.time,
+            }))
+        });
+
+        match result {
+            Ok(presp::Response::ActionResult(result)) => {
+                Response::ActionOutput(resp::ActionOutput {
+                    value: result.output,
+                    time: result.end,
+                })
+            }
+            Ok(presp::Response::Job(job)) => job_response(&target.device, job),
+            Ok(presp::Response::Error(error)) => action_error(error.message),
+            Ok(presp::Response::Violation(violation)) => {
+                violation_response(&target.device, violation)
+            }
+            Ok(response) => unexpected_response(&target.device, response),
+            Err(error) => instance_error(&target.device, error),
+        }
+    }
+
+    fn job(&self, target: &req::JobIdentifier, request: preq::Request) -> Response {
+        let job_error = |message: String| {
+            Response::JobError(resp::JobError {
+                message,
+                device: target.device.clone(),
+                job: target.job,
+            })
+        };
+
+        // retrieve the device plugin instance
+        let instance = match self.devices.get(&target.device) {
+            Some(instance) => instance,
+            None => return job_error("Device not found".to_string()),
+        };
+
+        match instance.process_request(request) {
+            Ok(presp::Response::Job(job)) => job_response(&target.device, job),
+            Ok(presp::Response::ActionResult(result)) => {
+                Response::ActionOutput(resp::ActionOutput {
+                    value: result.output,
+                    time: result.end,
+                })
+            }
+            Ok(presp::Response::Error(error)) => job_error(error.message),
+            Ok(presp::Response::Violation(violation)) => {
+                violation_response(&target.device, violation)
+            }
+            Ok(response) => unexpected_response(&target.device, response),
+            Err(error) => instance_error(&target.device, error),
+        }
+    }
+
+    fn job_status(&self, args: &req::JobStatus) -> Response {
+        let request = preq::Request::JobStatus(preq::JobStatus {
+            job: args.target.job,
+        });
+
+        self.job(&args.target, request)
+    }
+
+    fn job_result(&self, args: &req::JobResult) -> Response {
+        let request = preq::Request::JobResult(preq::JobResult {
+            job: args.target.job,
+        });
+
+        self.job(&args.target, request)
+    }
+
+    fn cancel_job(&self, args: &req::CancelJob) -> Response {
+        let target = &args.target;
+
+        // only ask plugins that can observe the request to cancel a job
+        let supported = self
+            .devices
+            .get(&target.device)
+            .and_then(|instance| instance.info())
+            .map(|info| {
+                info.capabilities
+                    .iter()
+                    .any(|c| c == CAPABILITY_CANCELLATION)
+            });
+
+        if supported == Some(false) {
+            return Response::JobError(resp::JobError {
+                message: "Device plugin does not support cancellation".to_string(),
+                device: target.device.clone(),
+                job: target.job,
+            });
+        }
+
+        let request = preq::Request::CancelJob(preq::CancelJob { job: target.job });
+        self.job(target, request)
+    }
+
+    /// Send the subscriber updates of an attribute until it unsubscribes or its
+    /// connection closes.
+    pub fn subscribe(&self, args: &req::Subscribe, subscriber: Subscriber) -> Response {
+        let target = &args.target;
+        let attribute_error = |message: String| {
+            Response::AttributeError(resp::AttributeError {
+                message,
+                device: target.device.clone(),
+                attribute: target.attribute.clone(),
+            })
+        };
+
+        if let Err(error) = self.subscribe_member(
+            &target.device,
+            &target.attribute,
+            subscriber,
+            attribute_error,
+        ) {
+            return error;
+        }
+
+        Response::Subscription(resp::Subscription {
+            device: target.device.clone(),
+            attribute: target.attribute.clone(),
+            active: true,
+        })
+    }
+
+    pub fn unsubscribe(&self, args: &req::Unsubscribe, connection: u64) -> Response {
+        let target = &args.target;
+        if !self.unsubscribe_member(&target.device, &target.attribute, connection) {
+            return Response::AttributeError(resp::AttributeError {
+                message: "Not subscribed".to_string(),
+                device: target.device.clone(),
+                attribute: target.attribute.clone(),
+            });
+        }
+
+        Response::Subscription(resp::Subscription {
+            device: target.device.clone(),
+            attribute: target.attribute.clone(),
+            active: false,
+        })
+    }
+
+    /// Send the subscriber occurrences of an event until it unsubscribes or its
+    /// connection closes.
+    pub fn subscribe_event(&self, args: &req::SubscribeEvent, subscriber: Subscriber) -> Response {
+        let target = &args.target;
+        let event_error = |message: String| {
+            Response::EventError(resp::EventError {
+                message,
+                device: target.device.clone(),
+                event: target.event.clone(),
+            })
+        };
+
+        if let Err(error) =
+            self.subscribe_member(&target.device, &target.event, subscriber, event_error)
+        {
+            return error;
+        }
+
+        Response::EventSubscription(resp::EventSubscription {
+            device: target.device.clone(),
+            event: target.event.clone(),
+            active: true,
+        })
+    }
+
+    pub fn unsubscribe_event(&self, args: &req::UnsubscribeEvent, connection: u64) -> Response {
+        let target = &args.target;
+        if !self.unsubscribe_member(&target.device, &target.event, connection) {
+            return Response::EventError(resp::EventError {
+                message: "Not subscribed".to_string(),
+                device: target.device.clone(),
+                event: target.event.clone(),
+            });
+        }
+
+        Response::EventSubscription(resp::EventSubscription {
+            device: target.device.clone(),
+            event: target.event.clone(),
+            active: false,
+        })
+    }
+
+    /// Add a subscriber to a device attribute or event and ask the plugin to
+    /// push its changes when it is the first one.
+    fn subscribe_member<E>(
+        &self,
+        device: &str,
+        path: &str,
+        subscriber: Subscriber,
+        member_error: E,
+    ) -> Result<(), Response>
+    where
+        E: Fn(String) -> Response,
+    {
+        // retrieve the device plugin instance
+        let instance = match self.devices.get(device) {
+            Some(instance) => instance,
+            None => return Err(member_error("Device not found".to_string())),
+        };
+
+        let monitored = self
+            .monitors
+            .contains_key(&(device.to_string(), path.to_string()));
+
+        let supported = instance.info().map(|info| {
+            info.capabilities
+                .iter()
+                .any(|c| c == CAPABILITY_SUBSCRIPTIONS)
+        });
+
+        if !monitored && supported == Some(false) {
+            return Err(member_error(
+                "Device plugin does not support subscriptions".to_string(),
+            ));
+        }
+
+        // the plugin is only asked once for every member, monitored
+        // attributes are published by their monitor instead
+        let lock = self.pending_lock(device, path);
+        let _guard = lock.lock().unwrap_or_else(PoisonError::into_inner);
+
+        if !monitored && !self.subscriptions.contains(device, path) {
+            match instance.subscribe(path) {
+                Ok(presp::Response::Status(_)) => {}
+                Ok(presp::Response::Error(error)) => return Err(member_error(error.message)),
+                Ok(response) => return Err(unexpected_response(device, response)),
+                Err(error) => return Err(instance_error(device, error)),
+            }
+        }
+
+        // only register the subscriber once the plugin sends updates
+        self.subscriptions.add(device, path, subscriber);
+        Ok(())
+    }
+
+    /// Lock that serializes subscribing to and releasing a member.
+    fn pending_lock(&self, device: &str, path: &str) -> Arc<Mutex<()>> {
+        let mut pending = self.pending.lock().unwrap_or_else(PoisonError::into_inner);
+        pending
+            .entry((device.to_string(), path.to_string()))
+            .or_default()
+            .clone()
+    }
+
+    /// Remove the subscription of a connection to a device attribute or event,
+    /// returns whether it was subscribed.
+    fn unsubscribe_member(&self, device: &str, path: &str, connection: u64) -> bool {
+        match self.subscriptions.remove(device, path, connection) {
+            Some(true) => self.release(device, path),
+            Some(false) => {}
+            None => return false,
+        }
+
+        true
+    }
+
+    /// Tell a client connection when the members of a device change.
+    pub fn add_connection(&self, connection: u64, sender: Sender<ResponseMessage>) {
+        self.subscriptions.add_connection(connection, sender);
+    }
+
+    /// Remove the subscriptions of a client connection that has closed.
+    pub fn drop_connection(&self, connection: u64) {
+        for (device, attribute) in self.subscriptions.remove_connection(connection) {
+            self.release(&device, &attribute);
+        }
+    }
+
+    /// Stop receiving updates for an attribute or event nobody is subscribed
+    /// to.
+    fn release(&self, device: &str, attribute: &str) {
+        let key = (device.to_string(), attribute.to_string());
+        if self.monitors.contains_key(&key) {
+            return;
+        }
+
+        // somebody may have subscribed again since the last subscriber left
+        let lock = self.pending_lock(device, attribute);
+        let _guard = lock.lock().unwrap_or_else(PoisonError::into_inner);
+        if self.subscriptions.contains(device, attribute) {
+            return;
+        }
+
+        if let Some(instance) = self.devices.get(device) {
+            match instance.unsubscribe(attribute) {
+                Ok(presp::Response::Status(_)) => {}
+                Ok(response) => eprintln!(
+                    "Failed to unsubscribe from {} {}: {:?}",
+                    device, attribute, response
+                ),
+                Err(error) => eprintln!(
+                    "Failed to unsubscribe from {} {}: {}",
+                    device, attribute, error
+                ),
+            }
+        }
+    }
+
+    fn health(&self) -> Response {
+        let mut devices: Vec<resp::DeviceHealth> = vec![];
+
+        for (name, instance) in self.devices.iter() {
+            let status = instance.status();
+            devices.push(resp::DeviceHealth {
+                device: name.clone(),
+                state: device_state(status.state),
+                missed_heartbeats: i32::try_from(status.missed_heartbeats).unwrap_or(i32::MAX),
+                restarts: i32::try_from(status.restarts).unwrap_or(i32::MAX),
+            });
+        }
+
+        Response::Health(resp::Health { devices })
+    }
+
+    /// Identify every device, those whose plugin cannot be reached are listed
+    /// without an identity.
+    fn inventory(&self) -> Response {
+        let mut devices: Vec<resp::DeviceInventory> = vec![];
+
+        for (name, instance) in self.devices.iter() {
+            let info = instance.info();
+            let status = instance.status();
+
+            let description = self.whole_description(name, instance).ok();
+
+            devices.push(resp::DeviceInventory {
+                device: name.clone(),
+                plugin: info.as_ref().map(|info| info.name.clone()),
+                plugin_version: info.map(|info| info.version),
+                state: device_state(status.state),
+                identity: description.map(|description| identity(description.identity)),
+            });
+        }
+
+        devices.sort_by(|a, b| a.device.cmp(&b.device));
+        Response::Inventory(resp::Inventory { devices })
+    }
+
+    pub fn process_request(&self, request: &Request) -> Response {
+        self.process_request_with_progress(request, |_progress| {})
+    }
+
+    /// Process a request and pass the intermediate results of the action it
+    /// runs to `progress` before returning the final response.
+    pub fn process_request_with_progress<F>(&self, request: &Request, progress: F) -> Response
+    where
+        F: FnMut(Response),
+    {
+        match request {
+            Request::Read(args) => self.read(args),
+            Request::Write(args) => self.write(args),
+            Request::Run(args) => self.run(args, progress),
+            Request::Health(_) => self.health(),
+            Request::Inventory(_) => self.inventory(),
+            Request::Describe(args) => self.describe(args),
+            Request::JobStatus(args) => self.job_status(args),
+            Request::JobResult(args) => self.job_result(args),
+            Request::CancelJob(args) => self.cancel_job(args),
+            Request::Subscribe(_)
+            | Request::Unsubscribe(_)
+            | Request::SubscribeEvent(_)
+            | Request::UnsubscribeEvent(_) => {
+                server_error("Subscriptions require a client connection".to_string())
+            }
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use std::error::Error;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+
+use avro_rs::{from_value, Reader, Schema, Writer};
+
+use crate::plugin::message_id;
+use crate::plugin::pool::WorkerPool;
+
+use super::config::Config;
+use super::request::{Request, RequestMessage};
+use super::response::{self as resp, Response, ResponseMessage};
+use super::router::Router;
+use super::subscriptions::Subscriber;
+
+/// Serves the public node protocol to clients.
+pub struct Server {
+    address: SocketAddr,
+    router: Arc<Router>,
+    pool: Arc<WorkerPool>,
+}
+
+fn write_responses(stream: TcpStream, receiver: Receiver<ResponseMessage>) {
+    let response_schema = Schema::parse_str(include_str!("response.avsc"))
+        .expect("Failed to parse response message schema");
+
+    let mut writer = Writer::new(&response_schema, &stream);
+
+    // send responses until every request has been processed
+    while let Ok(message) = receiver.recv() {
+        if let Err(error) = writer.append_ser(message) {
+            eprintln!("Failed to serialize response: {}", error);
+            continue;
+        }
+
+        if let Err(error) = writer.flush() {
+            eprintln!("Failed to send response: {}", error);
+            break;
+        }
+    }
+}
+
+fn handle_connection(
+    stream: TcpStream,
+    router: Arc<Router>,
+    pool: Arc<WorkerPool>,
+    connection: u64,
+) -> Result<(), Box<dyn Error>> {
+    let request_schema = Schema::parse_str(include_str!("request.avsc"))?;
+
+    // responses are sent as requests complete which is not necessarily the
+    // order in which they were received
+    let (sender, receiver) = channel();
+    let writer_stream = stream.try_clone()?;
+    let writer_handle = thread::spawn(move || {
+        write_responses(writer_stream, receiver);
+    });
+
+    router.add_connection(connection, sender.clone());
+
+    let result = read_requests(
+        &stream,
+        &request_schema,
+        &router,
+        &pool,
+        connection,
+        &sender,
+    );
+
+    // subscriptions would keep the response writer alive
+    router.drop_connection(connection);
+
+    // wait for outstanding requests to finish
+    drop(sender);
+    let _ = writer_handle.join();
+
+    result
+}
+
+fn read_requests(
+    stream: &TcpStream,
+    request_schema: &Schema,
+    router: &Arc<Router>,
+    pool: &WorkerPool,
+    connection: u64,
+    sender: &Sender<ResponseMessage>,
+) -> Result<(), Box<dyn Error>> {
+    let reader = Reader::with_schema(request_schema, stream)?;
+    for value in reader {
+        let value = value?;
+        let message = match from_value::<RequestMessage>(&value) {
+            Ok(message) => message,
+            Err(error) => {
+                let _ = sender.send(ResponseMessage {
+                    id: message_id(&value).unwrap_or(0),
+                    response: Response::ServerError(resp::ServerError {
+                        message: format!("Failed to unserialize request: {}", error),
+                    }),
+                });
+
+                continue;
+            }
+        };
+
+        // requests from every client share the pool so a flood of slow
+        // requests queues up instead of starting more threads
+        let router = router.clone();
+        let sender = sender.clone();
+        pool.execute(move || {
+            let response = match message.request {
+                Request::Subscribe(ref args) => {
+                    let subscriber = Subscriber {
+                        connection,
+                        id: message.id,
+                        sender: sender.clone(),
+                    };
+
+                    router.subscribe(args, subscriber)
+                }
+                Request::Unsubscribe(ref args) => router.unsubscribe(args, connection),
+                Request::SubscribeEvent(ref args) => {
+                    let subscriber = Subscriber {
+                        connection,
+                        id: message.id,
+                        sender: sender.clone(),
+                    };
+
+                    router.subscribe_event(args, subscriber)
+                }
+                Request::UnsubscribeEvent(ref args) => router.unsubscribe_event(args, connection),
+                ref request => router.process_request_with_progress(request, |progress| {
+                    let _ = sender.send(ResponseMessage {
+                        id: message.id,
+                        response: progress,
+                    });
+                }),
+            };
+
+            let _ = sender.send(ResponseMessage {
+                id: message.id,
+                response,
+            });
+        });
+    }
+
+    Ok(())
+}
+
+impl Server {
+    pub fn new(config: &Config) -> Result<Server, Box<dyn Error>> {
+        let address = SocketAddr::new(config.network.host, config.network.port);
+        let router = Arc::new(Router::new(config)?);
+        let pool = Arc::new(WorkerPool::new(config.network.workers));
+
+        Ok(Server {
+            address,
+            router,
+            pool,
+        })
+    }
+
+    pub fn run(&self) -> Result<(), Box<dyn Error>> {
+        let listener = TcpListener::bind(self.address)?;
+        println!("LISTENING {}", listener.local_addr()?);
+
+        let mut connection: u64 = 0;
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(error) => {
+                    eprintln!("Failed to accept connection: {}", error);
+                    continue;
+                }
+            };
+
+            connection += 1;
+            let router = self.router.clone();
+            let pool = self.pool.clone();
+            thread::spawn(move || {
+                let peer = stream.peer_addr().ok();
+                if let Err(error) = handle_connection(stream, router, pool, connection) {
+                    eprintln!("Connection {:?} closed: {}", peer, error);
+                }
+            });
+        }
+
+        Ok(())
+    }
+}
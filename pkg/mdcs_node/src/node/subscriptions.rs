@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+use std::sync::mpsc::Sender;
+use std::sync::Mutex;
+
+use crate::plugin::response as presp;
+
+use super::response::{self as resp, Response, ResponseMessage};
+
+/// Client connection that asked to receive updates of an attribute or
+/// occurrences of an event.
+#[derive(Debug)]
+pub struct Subscriber {
+    /// Connection the subscription belongs to.
+    pub connection: u64,
+    /// Identifier of the Subscribe request, updates are sent with it.
+    pub id: i64,
+    pub sender: Sender<ResponseMessage>,
+}
+
+/// Client connections subscribed to each device attribute and event.
+#[derive(Debug, Default)]
+pub struct Subscriptions {
+    targets: Mutex<HashMap<(String, String), Vec<Subscriber>>>,
+    /// Every client connection, told when the members of a device change.
+    clients: Mutex<HashMap<u64, Sender<ResponseMessage>>>,
+}
+
+impl Subscriptions {
+    pub fn new() -> Subscriptions {
+        Subscriptions::default()
+    }
+
+    /// Add a subscriber, returns whether it is the first one for the attribute.
+    pub fn add(&self, device: &str, attribute: &str, subscriber: Subscriber) -> bool {
+        let mut targets = self.targets.lock().unwrap();
+        let subscribers = targets
+            .entry((device.to_string(), attribute.to_string()))
+            .or_default();
+
+        // a connection only needs to subscribe once
+        subscribers.retain(|existing| existing.connection != subscriber.connection);
+        subscribers.push(subscriber);
+        subscribers.len() == 1
+    }
+
+    /// Check whether any connection is subscribed to an attribute.
+    pub fn contains(&self, device: &str, attribute: &str) -> bool {
+        let targets = self.targets.lock().unwrap();
+        targets.contains_key(&(device.to_string(), attribute.to_string()))
+    }
+
+    /// Remove the subscription of a connection, returns whether the attribute
+    /// has no subscribers left or `None` if the connection was not subscribed.
+    pub fn remove(&self, device: &str, attribute: &str, connection: u64) -> Option<bool> {
+        let mut targets = self.targets.lock().unwrap();
+        let key = (device.to_string(), attribute.to_string());
+        let subscribers = targets.get_mut(&key)?;
+
+        let count = subscribers.len();
+        subscribers.retain(|existing| existing.connection != connection);
+        if subscribers.len() == count {
+            return None;
+        }
+
+        if subscribers.is_empty() {
+            targets.remove(&key);
+            return Some(true);
+        }
+
+        Some(false)
+    }
+
+    /// Add a client connection to those told about device changes.
+    pub fn add_connection(&self, connection: u64, sender: Sender<ResponseMessage>) {
+        self.clients.lock().unwrap().insert(connection, sender);
+    }
+
+    /// Remove every subscription of a connection, returns the attributes that
+    /// have no subscribers left.
+    pub fn remove_connection(&self, connection: u64) -> Vec<(String, String)> {
+        self.clients.lock().unwrap().remove(&connection);
+
+        let mut targets = self.targets.lock().unwrap();
+        for subscribers in targets.values_mut() {
+            subscribers.retain(|existing| existing.connection != connection);
+        }
+
+        let unused: Vec<(String, String)> = targets
+            .iter()
+            .filter(|(_key, subscribers)| subscribers.is_empty())
+            .map(|(key, _subscribers)| key.clone())
+            .collect();
+
+        for key in unused.iter() {
+            targets.remove(key);
+        }
+
+        unused
+    }
+
+    /// Send an attribute update or event occurrence pushed by a device plugin
+    /// to its subscribers.
+    pub fn publish(&self, device: &str, update: presp::Response) {
+        let path = match update {
+            presp::Response::AttributeUpdate(ref update) => update.path.clone(),
+            presp::Response::EventOccurrence(ref occurrence) => occurrence.path.clone(),
+            _ => return,
+        };
+
+        let mut targets = self.targets.lock().unwrap();
+        if let Some(subscribers) = targets.get_mut(&(device.to_string(), path)) {
+            // forget connections that have gone away
+            subscribers.retain(|subscriber| match forwarded(device, &update) {
+                Some(response) => subscriber
+                    .sender
+                    .send(ResponseMessage {
+                        id: subscriber.id,
+                        response,
+                    })
+                    .is_ok(),
+                None => true,
+            });
+        }
+    }
+
+    /// Tell every client connection that members were added to or removed
+    /// from a device.
+    pub fn device_changed(&self, device: &str, version: i64) {
+        let mut clients = self.clients.lock().unwrap();
+
+        // changes are not associated with a request
+        clients.retain(|_connection, sender| {
+            let response = Response::DeviceChanged(resp::DeviceChanged {
+                device: device.to_string(),
+                version,
+            });
+
+            sender.send(ResponseMessage { id: 0, response }).is_ok()
+        });
+    }
+}
+
+/// Node response sent to subscribers for a message pushed by a device plugin.
+fn forwarded(device: &str, update: &presp::Response) -> Option<Response> {
+    match update {
+        presp::Response::AttributeUpdate(update) => {
+            Some(Response::AttributeUpdate(resp::AttributeUpdate {
+                device: device.to_string(),
+                attribute: update.path.clone(),
+                value: update.value.clone(),
+                time: update.time,
+            }))
+        }
+        presp::Response::EventOccurrence(occurrence) => Some(Response::Event(resp::Event {
+            device: device.to_string(),
+            event: occurrence.path.clone(),
+            payload: occurrence.payload.clone(),
+            time: occurrence.time,
+        })),
+        _ => None,
+    }
+}
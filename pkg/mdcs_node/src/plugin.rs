@@ -1,9 +1,57 @@
+pub mod client;
 pub mod config;
+pub mod error;
 pub mod instance;
+pub(crate) mod pool;
 pub mod request;
 pub mod response;
 pub mod server;
+pub mod transport;
 
-pub use config::InstanceConfig;
-pub use instance::Instance;
+pub use client::Client;
+pub use config::{HeartbeatConfig, InstanceConfig, RestartConfig};
+pub use error::InstanceError;
+pub use instance::{Instance, InstanceState, InstanceStatus, PluginInfo};
 pub use server::Server;
+pub use transport::Transport;
+
+use avro_rs::types::Value;
+
+/// Version of the node to plugin protocol, bumped on incompatible changes to
+/// the request or response schemas.
+pub const PROTOCOL_VERSION: i32 = 2;
+
+/// Plugin can push attribute updates to the node.
+pub const CAPABILITY_SUBSCRIPTIONS: &str = "subscriptions";
+
+/// Plugin can cancel running actions.
+pub const CAPABILITY_CANCELLATION: &str = "cancellation";
+
+/// Plugin reports when members are added to or removed from its device.
+pub const CAPABILITY_DEVICE_CHANGES: &str = "device-changes";
+
+/// Optional capabilities the node makes use of when the plugin supports them.
+pub const NODE_CAPABILITIES: &[&str] = &[
+    CAPABILITY_SUBSCRIPTIONS,
+    CAPABILITY_CANCELLATION,
+    CAPABILITY_DEVICE_CHANGES,
+];
+
+/// Optional capabilities implemented by the plugin server.
+pub const SERVER_CAPABILITIES: &[&str] = &[
+    CAPABILITY_SUBSCRIPTIONS,
+    CAPABILITY_CANCELLATION,
+    CAPABILITY_DEVICE_CHANGES,
+];
+
+/// Retrieve the correlation identifier from an encoded request or response
+/// message that could not be fully unserialized.
+pub(crate) fn message_id(value: &Value) -> Option<i64> {
+    match value {
+        Value::Record(fields) => fields.iter().find_map(|(name, value)| match value {
+            Value::Long(id) if name == "id" => Some(*id),
+            _ => None,
+        }),
+        _ => None,
+    }
+}
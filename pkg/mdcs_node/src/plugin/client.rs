@@ -0,0 +1,282 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use avro_rs::{from_value, Reader, Schema, Writer};
+
+use super::error::InstanceError;
+use super::message_id;
+use super::request::{Request, RequestMessage};
+use super::response::{ActionProgress, Response, ResponseMessage};
+use super::transport::{StreamReader, StreamWriter};
+
+type Reply = Sender<Result<Response, InstanceError>>;
+
+type UpdateHandler = Arc<dyn Fn(Response) + Send + Sync>;
+
+/// Node side of a plugin connection which can have several requests in
+/// flight and matches responses to them as they arrive.
+#[derive(Debug)]
+pub struct Client {
+    sender: Mutex<Sender<RequestMessage>>,
+    pending: Arc<Mutex<PendingRequests>>,
+    updates: Arc<Mutex<Updates>>,
+    next_id: AtomicI64,
+}
+
+/// Requests sent to the plugin that are waiting for a response.
+#[derive(Debug, Default)]
+struct PendingRequests {
+    closed: bool,
+    replies: HashMap<i64, Reply>,
+}
+
+impl PendingRequests {
+    fn close(&mut self) {
+        self.closed = true;
+        for (_id, reply) in self.replies.drain() {
+            let _ = reply.send(Err(InstanceError::Disconnected));
+        }
+    }
+}
+
+/// Receives the attribute updates, event occurrences and device changes the
+/// plugin pushes to the node.
+#[derive(Default)]
+struct Updates {
+    handler: Option<UpdateHandler>,
+}
+
+impl fmt::Debug for Updates {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("Updates")
+            .field("handler", &self.handler.is_some())
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+struct ClientContext {
+    pending: Arc<Mutex<PendingRequests>>,
+}
+
+impl Client {
+    pub fn new(stream_reader: StreamReader, stream_writer: StreamWriter) -> Client {
+        let pending = Arc::new(Mutex::new(PendingRequests::default()));
+        let updates = Arc::new(Mutex::new(Updates::default()));
+        let (sender, receiver) = channel();
+
+        let writer_context = ClientContext {
+            pending: pending.clone(),
+        };
+
+        thread::spawn(move || {
+            writer_context.write_requests(stream_writer, receiver);
+        });
+
+        let reader_context = ClientContext {
+            pending: pending.clone(),
+        };
+
+        let (update_sender, update_receiver) = channel();
+        thread::spawn(move || {
+            reader_context.read_responses(stream_reader, update_sender);
+        });
+
+        // updates are handled on their own thread so a slow handler does not
+        // delay the responses to pending requests
+        let dispatch_updates = updates.clone();
+        thread::spawn(move || {
+            while let Ok(update) = update_receiver.recv() {
+                let handler = dispatch_updates.lock().unwrap().handler.clone();
+                if let Some(handler) = handler {
+                    handler(update);
+                }
+            }
+        });
+
+        Client {
+            sender: Mutex::new(sender),
+            pending,
+            updates,
+            next_id: AtomicI64::new(1),
+        }
+    }
+
+    /// Pass the attribute updates, event occurrences and device changes pushed
+    /// by the plugin to `handler`.
+    pub fn on_update<F>(&self, handler: F)
+    where
+        F: Fn(Response) + Send + Sync + 'static,
+    {
+        self.updates.lock().unwrap().handler = Some(Arc::new(handler));
+    }
+
+    /// Whether the connection has failed or been closed.
+    pub fn is_closed(&self) -> bool {
+        self.pending.lock().unwrap().closed
+    }
+
+    /// Fail every outstanding request and refuse new ones.
+    pub fn close(&self) {
+        self.pending.lock().unwrap().close();
+    }
+
+    pub fn process_request(
+        &self,
+        request: Request,
+        timeout: Duration,
+    ) -> Result<Response, InstanceError> {
+        self.process_request_with_progress(request, timeout, |_progress| {})
+    }
+
+    /// Send a request and pass the intermediate results the plugin sends for
+    /// it to `progress` until the final response arrives, the timeout applies
+    /// to the time between consecutive messages.
+    pub fn process_request_with_progress<F>(
+        &self,
+        request: Request,
+        timeout: Duration,
+        mut progress: F,
+    ) -> Result<Response, InstanceError>
+    where
+        F: FnMut(ActionProgress),
+    {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let (reply, receiver) = channel();
+
+        // register the request before sending it so the response cannot
+        // arrive before anyone is waiting for it
+        {
+            let mut pending = self.pending.lock().unwrap();
+            if pending.closed {
+                return Err(InstanceError::Disconnected);
+            }
+
+            pending.replies.insert(id, reply);
+        }
+
+        let message = RequestMessage { id, request };
+        if self.sender.lock().unwrap().send(message).is_err() {
+            self.pending.lock().unwrap().replies.remove(&id);
+            return Err(InstanceError::Disconnected);
+        }
+
+        // wait for the plugin to respond
+        loop {
+            match receiver.recv_timeout(timeout) {
+                Ok(Ok(Response::ActionProgress(event))) => progress(event),
+                Ok(result) => return result,
+                Err(RecvTimeoutError::Timeout) => {
+                    // a late response will be discarded by the reader
+                    self.pending.lock().unwrap().replies.remove(&id);
+                    return Err(InstanceError::Timeout);
+                }
+                Err(RecvTimeoutError::Disconnected) => return Err(InstanceError::Disconnected),
+            }
+        }
+    }
+}
+
+impl ClientContext {
+    fn write_requests(&self, stream: StreamWriter, receiver: Receiver<RequestMessage>) {
+        // parse the plugin request schema
+        let request_schema = Schema::parse_str(include_str!("request.avsc"))
+            .expect("Failed to parse request message schema");
+
+        let mut writer = Writer::new(&request_schema, stream);
+
+        // send requests until the instance is dropped
+        while let Ok(message) = receiver.recv() {
+            if let Err(error) = writer.append_ser(&message) {
+                // a request that cannot be encoded does not affect the connection
+                let reply = self.pending.lock().unwrap().replies.remove(&message.id);
+                if let Some(reply) = reply {
+                    let _ = reply.send(Err(InstanceError::Encode(format!("{}", error))));
+                }
+
+                continue;
+            }
+
+            if let Err(error) = writer.flush() {
+                eprintln!("Failed to send request to plugin: {}", error);
+                break;
+            }
+        }
+
+        self.pending.lock().unwrap().close();
+    }
+
+    fn read_responses(&self, stream: StreamReader, updates: Sender<Response>) {
+        // parse the plugin response schema
+        let response_schema = Schema::parse_str(include_str!("response.avsc"))
+            .expect("Failed to parse response message schema");
+
+        // the plugin sends the response stream header along with the first response
+        let reader = match Reader::with_schema(&response_schema, stream) {
+            Ok(reader) => reader,
+            Err(error) => {
+                eprintln!("Failed to create Avro response reader: {}", error);
+                self.pending.lock().unwrap().close();
+                return;
+            }
+        };
+
+        // dispatch responses to whoever is waiting for them
+        for value in reader {
+            let value = match value {
+                Ok(value) => value,
+                Err(error) => {
+                    // the response stream is no longer in a known state
+                    eprintln!("Failed to read response from plugin: {}", error);
+                    break;
+                }
+            };
+
+            let (id, response) = match from_value::<ResponseMessage>(&value) {
+                Ok(message) => (Some(message.id), Ok(message.response)),
+                Err(error) => (
+                    message_id(&value),
+                    Err(InstanceError::Decode(format!("{}", error))),
+                ),
+            };
+
+            // updates are not a response to any pending request
+            let response = match response {
+                Ok(update @ Response::AttributeUpdate(_))
+                | Ok(update @ Response::EventOccurrence(_))
+                | Ok(update @ Response::DeviceChanged(_)) => {
+                    let _ = updates.send(update);
+                    continue;
+                }
+                response => response,
+            };
+
+            // intermediate results are followed by more messages for the request
+            let reply = id.and_then(|id| {
+                let mut pending = self.pending.lock().unwrap();
+                match response {
+                    Ok(Response::ActionProgress(_)) => pending.replies.get(&id).cloned(),
+                    _ => pending.replies.remove(&id),
+                }
+            });
+
+            match reply {
+                Some(reply) => {
+                    let _ = reply.send(response);
+                }
+                None => {
+                    if let Err(error) = response {
+                        eprintln!("Discarding response: {}", error);
+                    }
+                }
+            }
+        }
+
+        self.pending.lock().unwrap().close();
+    }
+}
@@ -1,6 +1,72 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize)]
+use super::transport::Transport;
+
+fn default_timeout() -> u64 {
+    5000
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RestartConfig {
+    /// Number of consecutive restart attempts before giving up.
+    pub max_retries: u32,
+
+    /// Delay before the first restart attempt in milliseconds.
+    pub initial_backoff: u64,
+
+    /// Upper bound on the delay between restart attempts in milliseconds.
+    pub max_backoff: u64,
+}
+
+impl Default for RestartConfig {
+    fn default() -> RestartConfig {
+        RestartConfig {
+            max_retries: 5,
+            initial_backoff: 500,
+            max_backoff: 30000,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HeartbeatConfig {
+    /// Interval between heartbeats in milliseconds.
+    pub interval: u64,
+
+    /// Time to wait for a heartbeat response in milliseconds.
+    pub timeout: u64,
+
+    /// Number of consecutive missed heartbeats before the plugin is
+    /// considered unhealthy and restarted.
+    pub max_missed: u32,
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> HeartbeatConfig {
+        HeartbeatConfig {
+            interval: 1000,
+            timeout: 2000,
+            max_missed: 3,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct InstanceConfig {
     pub command: String,
+
+    #[serde(default)]
+    pub transport: Transport,
+
+    /// Request timeout in milliseconds.
+    #[serde(default = "default_timeout")]
+    pub timeout: u64,
+
+    #[serde(default)]
+    pub restart: RestartConfig,
+
+    #[serde(default)]
+    pub heartbeat: HeartbeatConfig,
 }
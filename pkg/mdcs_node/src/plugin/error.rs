@@ -0,0 +1,43 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum InstanceError {
+    Io(io::Error),
+    Timeout,
+    Disconnected,
+    NotRunning,
+    Incompatible(String),
+    Encode(String),
+    Decode(String),
+}
+
+impl fmt::Display for InstanceError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InstanceError::Io(ref error) => write!(fmt, "IO error: {}", error),
+            InstanceError::Timeout => write!(fmt, "Plugin did not respond in time"),
+            InstanceError::Disconnected => write!(fmt, "Plugin connection closed"),
+            InstanceError::NotRunning => write!(fmt, "Plugin is not running"),
+            InstanceError::Incompatible(msg) => write!(fmt, "Incompatible plugin: {}", msg),
+            InstanceError::Encode(msg) => write!(fmt, "Failed to encode request: {}", msg),
+            InstanceError::Decode(msg) => write!(fmt, "Failed to decode response: {}", msg),
+        }
+    }
+}
+
+impl Error for InstanceError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            InstanceError::Io(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for InstanceError {
+    fn from(err: io::Error) -> InstanceError {
+        InstanceError::Io(err)
+    }
+}
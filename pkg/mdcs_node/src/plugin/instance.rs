@@ -1,22 +1,32 @@
-use std::io::{self, BufRead, BufReader, ErrorKind, Result};
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, BufReader, ErrorKind};
 use std::net::TcpStream;
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
 use std::process::{Child, Command, Stdio};
-use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
 use std::thread::{self, JoinHandle};
-use std::time::Duration;
-
-use avro_rs::{from_value, Reader, Schema, Writer};
+use std::time::{Duration, Instant};
 
+use super::client::Client;
 use super::config::InstanceConfig;
-use super::request::Request;
-use super::response::Response;
+use super::error::InstanceError;
+use super::request::{self as req, Request, Signal};
+use super::response::{ActionProgress, Response};
+use super::transport::{self, StreamReader, StreamWriter, Transport};
+use super::{NODE_CAPABILITIES, PROTOCOL_VERSION};
+
+type UpdateHandler = Arc<dyn Fn(Response) + Send + Sync>;
 
-fn initial_connect(child: &mut Child) -> Result<TcpStream> {
+fn read_listen_line(child: &mut Child) -> io::Result<String> {
     let child_stdout = child
         .stdout
         .as_mut()
-        .expect("Failed to retrieve child stdout");
+        .ok_or_else(|| io::Error::new(ErrorKind::BrokenPipe, "child stdout not captured"))?;
 
     let mut reader = BufReader::new(child_stdout);
     let mut listen_line = String::new();
@@ -36,125 +46,576 @@ fn initial_connect(child: &mut Child) -> Result<TcpStream> {
         ));
     }
 
-    let address = listen_line.split(' ').nth(1).ok_or(io::Error::new(
-        ErrorKind::InvalidData,
-        "received invalid LISTENING line",
-    ))?;
+    let address = listen_line
+        .trim_end()
+        .split_once(' ')
+        .map(|(_, address)| address)
+        .ok_or(io::Error::new(
+            ErrorKind::InvalidData,
+            "received invalid LISTENING line",
+        ))?;
+
+    Ok(address.to_string())
+}
 
-    TcpStream::connect(address)
+fn initial_connect(
+    child: &mut Child,
+    transport: Transport,
+    socket_path: Option<&Path>,
+) -> io::Result<(StreamReader, StreamWriter)> {
+    match transport {
+        Transport::Tcp => {
+            let address = read_listen_line(child)?;
+            let stream = TcpStream::connect(address)?;
+            Ok((Box::new(stream.try_clone()?), Box::new(stream)))
+        }
+        #[cfg(unix)]
+        Transport::Unix => {
+            read_listen_line(child)?;
+            let path = socket_path
+                .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "missing socket path"))?;
+            let stream = UnixStream::connect(path)?;
+            Ok((Box::new(stream.try_clone()?), Box::new(stream)))
+        }
+        #[cfg(not(unix))]
+        Transport::Unix => Err(io::Error::new(
+            ErrorKind::Other,
+            "Unix domain sockets are not supported on this platform",
+        )),
+        Transport::Stdio => match (child.stdout.take(), child.stdin.take()) {
+            (Some(stdout), Some(stdin)) => Ok((Box::new(stdout), Box::new(stdin))),
+            _ => Err(io::Error::new(
+                ErrorKind::BrokenPipe,
+                "child stdin and stdout not captured",
+            )),
+        },
+    }
+}
+
+/// Interval at which the supervisor checks on the plugin process.
+const SUPERVISOR_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InstanceState {
+    Starting,
+    Running,
+    Unhealthy,
+    BackingOff,
+    Failed,
+}
+
+impl fmt::Display for InstanceState {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InstanceState::Starting => write!(fmt, "starting"),
+            InstanceState::Running => write!(fmt, "running"),
+            InstanceState::Unhealthy => write!(fmt, "unhealthy"),
+            InstanceState::BackingOff => write!(fmt, "backing off"),
+            InstanceState::Failed => write!(fmt, "failed"),
+        }
+    }
 }
 
+/// Plugin details exchanged during the initial handshake.
+#[derive(Clone, Debug)]
+pub struct PluginInfo {
+    pub name: String,
+    pub version: String,
+
+    /// Capabilities supported by both the node and the plugin.
+    pub capabilities: Vec<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct InstanceStatus {
+    pub state: InstanceState,
+    pub missed_heartbeats: u32,
+    pub restarts: u32,
+}
+
+/// Plugin process supervised by a background thread that restarts it when it
+/// exits or its connection fails.
 #[derive(Debug)]
 pub struct Instance {
     config: InstanceConfig,
-    child: Child,
-    handle: JoinHandle<()>,
-    sender: Sender<Arc<InstanceMessage>>,
+    shared: Arc<InstanceShared>,
+    supervisor_handle: Option<JoinHandle<()>>,
 }
 
-#[derive(Debug)]
-struct InstanceMessage {
-    request: Request,
-    response: Option<Response>,
+struct InstanceShared {
+    status: Mutex<InstanceStatus>,
+    connection: RwLock<Option<Arc<Connection>>>,
+    quit: AtomicBool,
+    subscriptions: Mutex<HashSet<String>>,
+    updates: Arc<Mutex<Option<UpdateHandler>>>,
+}
+
+impl fmt::Debug for InstanceShared {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("InstanceShared")
+            .field("status", &self.status)
+            .field("connection", &self.connection)
+            .field("quit", &self.quit)
+            .field("subscriptions", &self.subscriptions)
+            .finish()
+    }
 }
 
+/// Running plugin process and the connection to it.
 #[derive(Debug)]
-struct InstanceContext {
-    stream: TcpStream,
-    receiver: Receiver<Arc<InstanceMessage>>,
+struct Connection {
+    info: PluginInfo,
+    child: Mutex<Child>,
+    runtime_dir: Option<PathBuf>,
+    client: Client,
 }
 
 impl Instance {
-    pub fn new(config: InstanceConfig) -> Result<Instance> {
-        let mut child = Command::new(&config.command)
-            .stdout(Stdio::piped())
-            .spawn()?;
+    pub fn new(config: InstanceConfig) -> Result<Instance, InstanceError> {
+        // the first start is not retried so configuration errors surface early
+        let connection = Connection::start(&config)?;
+        let shared = Arc::new(InstanceShared {
+            status: Mutex::new(InstanceStatus {
+                state: InstanceState::Running,
+                missed_heartbeats: 0,
+                restarts: 0,
+            }),
+            connection: RwLock::new(None),
+            quit: AtomicBool::new(false),
+            subscriptions: Mutex::new(HashSet::new()),
+            updates: Arc::new(Mutex::new(None)),
+        });
 
-        let stream = initial_connect(&mut child)?;
-        let (sender, receiver) = channel();
-        let mut context = InstanceContext { stream, receiver };
+        shared.attach(&config, connection);
 
-        let handle = thread::spawn(move || {
-            context.run();
+        let supervisor_config = config.clone();
+        let supervisor_shared = shared.clone();
+        let supervisor_handle = thread::spawn(move || {
+            supervisor_shared.supervise(&supervisor_config);
         });
 
         Ok(Instance {
             config,
-            child,
-            handle,
-            sender,
+            shared,
+            supervisor_handle: Some(supervisor_handle),
         })
     }
 
-    pub fn process_request(&mut self, request: Request) -> Response {
-        let message = Arc::new(InstanceMessage {
-            request,
-            response: None,
+    pub fn state(&self) -> InstanceState {
+        self.shared.status.lock().unwrap().state
+    }
+
+    pub fn status(&self) -> InstanceStatus {
+        self.shared.status.lock().unwrap().clone()
+    }
+
+    /// Details of the running plugin, if any.
+    pub fn info(&self) -> Option<PluginInfo> {
+        let connection = self.shared.connection.read().unwrap();
+        connection
+            .as_ref()
+            .map(|connection| connection.info.clone())
+    }
+
+    /// Pass the attribute updates, event occurrences and device changes pushed
+    /// by the plugin to `handler`, they keep arriving across plugin restarts.
+    pub fn set_update_handler<F>(&self, handler: F)
+    where
+        F: Fn(Response) + Send + Sync + 'static,
+    {
+        *self.shared.updates.lock().unwrap() = Some(Arc::new(handler));
+    }
+
+    /// Subscribe to updates of an attribute or occurrences of an event, the
+    /// subscription is restored when the plugin is restarted.
+    pub fn subscribe(&self, path: &str) -> Result<Response, InstanceError> {
+        let request = Request::Subscribe(req::Subscribe {
+            path: path.to_string(),
         });
 
-        self.sender
-            .send(message.clone())
-            .expect("Failed to send message");
+        let response = self.process_request(request)?;
+        if let Response::Status(_) = response {
+            self.shared
+                .subscriptions
+                .lock()
+                .unwrap()
+                .insert(path.to_string());
+        }
+
+        Ok(response)
+    }
 
-        // TODO: wait for command to finish or time out
-        thread::sleep(Duration::from_millis(1000 * 5));
+    pub fn unsubscribe(&self, path: &str) -> Result<Response, InstanceError> {
+        self.shared.subscriptions.lock().unwrap().remove(path);
+        let request = Request::Unsubscribe(req::Unsubscribe {
+            path: path.to_string(),
+        });
 
-        let message = Arc::try_unwrap(message).expect("Existing references to Arc");
-        message.response.expect("Failed to process request")
+        self.process_request(request)
+    }
+
+    pub fn process_request(&self, request: Request) -> Result<Response, InstanceError> {
+        self.process_request_with_progress(request, |_progress| {})
+    }
+
+    /// Send a request to the plugin and pass the intermediate results of the
+    /// action it runs to `progress`.
+    pub fn process_request_with_progress<F>(
+        &self,
+        request: Request,
+        progress: F,
+    ) -> Result<Response, InstanceError>
+    where
+        F: FnMut(ActionProgress),
+    {
+        let connection = self.shared.connection.read().unwrap().clone();
+        let connection = connection.ok_or(InstanceError::NotRunning)?;
+        let timeout = Duration::from_millis(self.config.timeout);
+
+        connection
+            .client
+            .process_request_with_progress(request, timeout, progress)
     }
 }
 
-impl InstanceContext {
-    fn run(&mut self) {
-        // parse the plugin message schemas
-        let request_schema = Schema::parse_str(include_str!("request.avsc"))
-            .expect("Failed to parse request message schema");
+impl Drop for Instance {
+    fn drop(&mut self) {
+        self.shared.quit.store(true, Ordering::SeqCst);
+        if let Some(handle) = self.supervisor_handle.take() {
+            let _ = handle.join();
+        }
+
+        if let Some(connection) = self.shared.connection.write().unwrap().take() {
+            connection.kill();
+        }
+    }
+}
+
+impl InstanceShared {
+    /// Start using a new plugin connection and restore the subscriptions made
+    /// through the previous one.
+    fn attach(&self, config: &InstanceConfig, connection: Connection) {
+        let updates = self.updates.clone();
+        connection.client.on_update(move |update| {
+            let handler = updates.lock().unwrap().clone();
+            if let Some(handler) = handler {
+                handler(update);
+            }
+        });
+
+        let timeout = Duration::from_millis(config.timeout);
+        let paths: Vec<String> = self.subscriptions.lock().unwrap().iter().cloned().collect();
+        for path in paths {
+            let request = Request::Subscribe(req::Subscribe { path: path.clone() });
+            match connection.client.process_request(request, timeout) {
+                Ok(Response::Status(_)) => {}
+                Ok(response) => {
+                    eprintln!("Failed to restore subscription {}: {:?}", path, response)
+                }
+                Err(error) => eprintln!("Failed to restore subscription {}: {}", path, error),
+            }
+        }
+
+        *self.connection.write().unwrap() = Some(Arc::new(connection));
+    }
+
+    fn set_state(&self, command: &str, state: InstanceState) {
+        eprintln!("Plugin {} is {}", command, state);
+        self.status.lock().unwrap().state = state;
+    }
+
+    /// Send a heartbeat to the plugin and return whether it is still
+    /// considered healthy.
+    fn heartbeat(&self, connection: &Connection, config: &InstanceConfig) -> bool {
+        let request = Request::Signal(Signal::Ping);
+        let timeout = Duration::from_millis(config.heartbeat.timeout);
+
+        let missed = match connection.client.process_request(request, timeout) {
+            Ok(Response::Status(_)) => {
+                self.status.lock().unwrap().missed_heartbeats = 0;
+                return true;
+            }
+            Err(InstanceError::Timeout) => {
+                let mut status = self.status.lock().unwrap();
+                status.missed_heartbeats += 1;
+                status.missed_heartbeats
+            }
+            Ok(response) => {
+                eprintln!(
+                    "Plugin {} answered heartbeat with {:?}",
+                    config.command, response
+                );
 
-        let response_schema = Schema::parse_str(include_str!("response.avsc"))
-            .expect("Failed to parse response message schema");
+                self.set_state(&config.command, InstanceState::Unhealthy);
+                return false;
+            }
+            Err(error) => {
+                // a connection that cannot carry heartbeats is not healthy
+                eprintln!("Plugin {} failed heartbeat: {}", config.command, error);
+                self.set_state(&config.command, InstanceState::Unhealthy);
+                return false;
+            }
+        };
 
-        // create request writer and response reader
-        let mut writer = Writer::new(&request_schema, &self.stream);
-        let mut reader = Reader::with_schema(&response_schema, &self.stream)
-            .expect("Failed to create Avro response reader");
+        eprintln!("Plugin {} missed {} heartbeat(s)", config.command, missed);
+
+        if missed >= config.heartbeat.max_missed {
+            self.set_state(&config.command, InstanceState::Unhealthy);
+            return false;
+        }
+
+        true
+    }
+
+    /// Sleep for the given duration unless the instance is dropped first.
+    fn wait(&self, duration: Duration) -> bool {
+        let deadline = Instant::now() + duration;
+        while Instant::now() < deadline {
+            if self.quit.load(Ordering::SeqCst) {
+                return false;
+            }
+
+            thread::sleep(SUPERVISOR_INTERVAL);
+        }
+
+        !self.quit.load(Ordering::SeqCst)
+    }
+
+    fn supervise(&self, config: &InstanceConfig) {
+        let restart = &config.restart;
+        let mut retries = 0;
+        let mut started = Instant::now();
 
-        // process requests
         loop {
-            let mut message = match self.receiver.try_recv() {
-                Ok(message) => message,
-                Err(TryRecvError::Empty) => {
-                    // rate limit
-                    thread::sleep(Duration::from_millis(100));
+            // wait for the plugin to exit, its connection to fail, or it to
+            // stop responding to heartbeats
+            let heartbeat_interval = Duration::from_millis(config.heartbeat.interval);
+            let mut last_heartbeat = Instant::now();
 
-                    // try again
+            while self.wait(SUPERVISOR_INTERVAL) {
+                let connection = match self.connection.read().unwrap().clone() {
+                    Some(connection) if connection.is_alive() => connection,
+                    _ => break,
+                };
+
+                if last_heartbeat.elapsed() < heartbeat_interval {
                     continue;
                 }
-                Err(TryRecvError::Disconnected) => {
-                    // time to quit
+
+                last_heartbeat = Instant::now();
+                if !self.heartbeat(&connection, config) {
                     break;
                 }
+            }
+
+            if self.quit.load(Ordering::SeqCst) {
+                return;
+            }
+
+            // tear down the failed connection
+            if let Some(connection) = self.connection.write().unwrap().take() {
+                connection.kill();
+            }
+
+            eprintln!("Plugin {} stopped", config.command);
+
+            // a plugin that ran for a while before failing starts over with
+            // a fresh retry budget
+            if started.elapsed() >= Duration::from_millis(restart.max_backoff) {
+                retries = 0;
+            }
+
+            // restart the plugin with exponential backoff
+            loop {
+                if retries >= restart.max_retries {
+                    eprintln!(
+                        "Plugin {} failed {} times, giving up",
+                        config.command, retries
+                    );
+
+                    self.set_state(&config.command, InstanceState::Failed);
+                    return;
+                }
+
+                let backoff = restart
+                    .initial_backoff
+                    .saturating_mul(1 << retries.min(16))
+                    .min(restart.max_backoff);
+
+                retries += 1;
+                self.set_state(&config.command, InstanceState::BackingOff);
+                if !self.wait(Duration::from_millis(backoff)) {
+                    return;
+                }
+
+                self.set_state(&config.command, InstanceState::Starting);
+                match Connection::start(config) {
+                    Ok(connection) => {
+                        self.attach(config, connection);
+                        {
+                            let mut status = self.status.lock().unwrap();
+                            status.missed_heartbeats = 0;
+                            status.restarts += 1;
+                        }
+
+                        self.set_state(&config.command, InstanceState::Running);
+                        started = Instant::now();
+                        break;
+                    }
+                    Err(error) => {
+                        eprintln!("Failed to restart plugin {}: {}", config.command, error);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Connection {
+    fn start(config: &InstanceConfig) -> Result<Connection, InstanceError> {
+        // the plugin only accepts a connection that presents this secret
+        let token = transport::generate_token()?;
+
+        let mut command = Command::new(&config.command);
+        command
+            .env(transport::TRANSPORT_VARIABLE, config.transport.name())
+            .env(transport::TOKEN_VARIABLE, &token)
+            .stdout(Stdio::piped());
+
+        // the plugin socket lives in a directory only the node user can access
+        let runtime_dir = match config.transport {
+            Transport::Unix => Some(transport::create_runtime_dir()?),
+            _ => None,
+        };
+
+        let socket_path = runtime_dir.as_ref().map(|dir| dir.join("plugin.sock"));
+        if let Some(ref socket_path) = socket_path {
+            command.env(transport::SOCKET_VARIABLE, socket_path);
+        }
+
+        if config.transport == Transport::Stdio {
+            command.stdin(Stdio::piped());
+        }
+
+        let mut child = match command.spawn() {
+            Ok(child) => child,
+            Err(error) => {
+                if let Some(ref runtime_dir) = runtime_dir {
+                    let _ = fs::remove_dir_all(runtime_dir);
+                }
+
+                return Err(error.into());
+            }
+        };
+
+        let (stream_reader, stream_writer) =
+            match initial_connect(&mut child, config.transport, socket_path.as_deref()) {
+                Ok(streams) => streams,
+                Err(error) => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    if let Some(ref runtime_dir) = runtime_dir {
+                        let _ = fs::remove_dir_all(runtime_dir);
+                    }
+
+                    return Err(error.into());
+                }
             };
 
-            // XXX: not sure if this is the best way
-            let message =
-                Arc::get_mut(&mut message).expect("Failed to get mutable message reference");
+        let client = Client::new(stream_reader, stream_writer);
+
+        let mut connection = Connection {
+            info: PluginInfo {
+                name: config.command.clone(),
+                version: String::new(),
+                capabilities: vec![],
+            },
+            child: Mutex::new(child),
+            runtime_dir,
+            client,
+        };
+
+        match connection.handshake(config, token) {
+            Ok(info) => {
+                connection.info = info;
+                Ok(connection)
+            }
+            Err(error) => {
+                connection.kill();
+                Err(error)
+            }
+        }
+    }
+
+    /// Exchange protocol versions and capabilities with the plugin.
+    fn handshake(
+        &self,
+        config: &InstanceConfig,
+        token: String,
+    ) -> Result<PluginInfo, InstanceError> {
+        let request = Request::Hello(req::Hello {
+            protocol_version: PROTOCOL_VERSION,
+            node_version: env!("CARGO_PKG_VERSION").to_string(),
+            capabilities: NODE_CAPABILITIES.iter().map(|c| c.to_string()).collect(),
+            token,
+        });
+
+        let timeout = Duration::from_millis(config.timeout);
+        let hello = match self.client.process_request(request, timeout)? {
+            Response::Hello(hello) => hello,
+            Response::Error(error) => {
+                return Err(InstanceError::Incompatible(format!(
+                    "plugin {} rejected the handshake: {}",
+                    config.command, error.message
+                )));
+            }
+            response => {
+                return Err(InstanceError::Incompatible(format!(
+                    "plugin {} sent an unexpected handshake response: {:?}",
+                    config.command, response
+                )));
+            }
+        };
+
+        if hello.protocol_version != PROTOCOL_VERSION {
+            return Err(InstanceError::Incompatible(format!(
+                "plugin {} {} speaks protocol version {}, node requires version {}",
+                hello.plugin_name, hello.plugin_version, hello.protocol_version, PROTOCOL_VERSION
+            )));
+        }
 
-            // send the request
-            writer
-                .append_ser(&message.request)
-                .expect("Failed to serialize request");
-            writer.flush().expect("Failed to flush writer");
+        let capabilities = hello
+            .capabilities
+            .into_iter()
+            .filter(|capability| NODE_CAPABILITIES.contains(&capability.as_str()))
+            .collect();
 
-            // receive the response
-            let value = reader
-                .next()
-                .expect("Failed to read response")
-                .expect("Failed to unserialize response");
+        Ok(PluginInfo {
+            name: hello.plugin_name,
+            version: hello.plugin_version,
+            capabilities,
+        })
+    }
+
+    fn is_alive(&self) -> bool {
+        if self.client.is_closed() {
+            return false;
+        }
+
+        match self.child.lock().unwrap().try_wait() {
+            Ok(None) => true,
+            Ok(Some(_)) | Err(_) => false,
+        }
+    }
 
-            message.response =
-                Some(from_value::<Response>(&value).expect("Failed to unserialize response"));
+    fn kill(&self) {
+        let mut child = self.child.lock().unwrap();
+        let _ = child.kill();
+        let _ = child.wait();
 
-            // TODO: notify whoever is waiting the command is complete
+        self.client.close();
+        if let Some(ref runtime_dir) = self.runtime_dir {
+            let _ = fs::remove_dir_all(runtime_dir);
         }
     }
 }
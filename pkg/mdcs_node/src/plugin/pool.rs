@@ -0,0 +1,68 @@
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+type Task = Box<dyn FnOnce() + Send>;
+
+/// Fixed number of threads that run tasks in the order they are submitted.
+pub struct WorkerPool {
+    sender: Option<Mutex<Sender<Task>>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+fn run_tasks(receiver: Arc<Mutex<Receiver<Task>>>) {
+    loop {
+        // only hold the lock while waiting so other workers can pick up tasks
+        let task = match receiver.lock() {
+            Ok(receiver) => receiver.recv(),
+            Err(_) => break,
+        };
+
+        match task {
+            Ok(task) => task(),
+            Err(_) => break,
+        }
+    }
+}
+
+impl WorkerPool {
+    pub fn new(size: usize) -> WorkerPool {
+        let (sender, receiver) = channel();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let workers = (0..size.max(1))
+            .map(|_| {
+                let receiver = receiver.clone();
+                thread::spawn(move || run_tasks(receiver))
+            })
+            .collect();
+
+        WorkerPool {
+            sender: Some(Mutex::new(sender)),
+            workers,
+        }
+    }
+
+    /// Queue a task to run on the next available worker.
+    pub fn execute<F>(&self, task: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        if let Some(ref sender) = self.sender {
+            if let Ok(sender) = sender.lock() {
+                let _ = sender.send(Box::new(task));
+            }
+        }
+    }
+}
+
+impl Drop for WorkerPool {
+    fn drop(&mut self) {
+        // closing the channel lets the workers finish queued tasks and exit
+        self.sender.take();
+
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
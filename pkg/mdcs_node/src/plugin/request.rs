@@ -3,10 +3,22 @@ use serde::{Deserialize, Serialize};
 #[derive(Debug, Serialize, Deserialize)]
 pub enum Signal {
     Quit,
+    Ping,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-pub struct DescribeDevice {}
+pub struct Hello {
+    pub protocol_version: i32,
+    pub node_version: String,
+    pub capabilities: Vec<String>,
+    pub token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DescribeDevice {
+    #[serde(default)]
+    pub path: Option<String>,
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ReadAttribute {
@@ -23,6 +35,33 @@ pub struct WriteAttribute {
 pub struct RunAction {
     pub path: String,
     pub input: Vec<u8>,
+    #[serde(default)]
+    pub background: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JobStatus {
+    pub job: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JobResult {
+    pub job: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CancelJob {
+    pub job: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Subscribe {
+    pub path: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Unsubscribe {
+    pub path: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -32,4 +71,16 @@ pub enum Request {
     ReadAttribute(ReadAttribute),
     WriteAttribute(WriteAttribute),
     RunAction(RunAction),
+    Hello(Hello),
+    JobStatus(JobStatus),
+    JobResult(JobResult),
+    CancelJob(CancelJob),
+    Subscribe(Subscribe),
+    Unsubscribe(Unsubscribe),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RequestMessage {
+    pub id: i64,
+    pub request: Request,
 }
@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -11,24 +13,57 @@ pub struct Error {
     pub path: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Metadata {
+    pub description: Option<String>,
+    pub unit: Option<String>,
+    pub minimum: Option<f64>,
+    pub maximum: Option<f64>,
+    pub step: Option<f64>,
+    pub labels: HashMap<String, String>,
+    pub precision: Option<i32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Attribute {
     pub path: String,
     pub flags: Vec<String>,
     pub schema: String,
+    pub metadata: Metadata,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Action {
     pub path: String,
     pub input_schema: String,
     pub output_schema: String,
+    pub progress_schema: String,
+    pub metadata: Metadata,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Event {
+    pub path: String,
+    pub schema: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Identity {
+    pub name: Option<String>,
+    pub vendor: Option<String>,
+    pub model: Option<String>,
+    pub serial: Option<String>,
+    pub firmware_version: Option<String>,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Device {
     pub attributes: Vec<Attribute>,
     pub actions: Vec<Action>,
+    pub events: Vec<Event>,
+    pub identity: Identity,
+    pub version: i64,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -44,11 +79,82 @@ pub struct ActionResult {
     pub end: i64,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Hello {
+    pub protocol_version: i32,
+    pub plugin_name: String,
+    pub plugin_version: String,
+    pub capabilities: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum JobState {
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Job {
+    pub job: i64,
+    pub path: String,
+    pub state: JobState,
+    pub progress: f64,
+    pub message: Option<String>,
+    pub event: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ActionProgress {
+    pub value: Vec<u8>,
+    pub time: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AttributeUpdate {
+    pub path: String,
+    pub value: Vec<u8>,
+    pub time: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EventOccurrence {
+    pub path: String,
+    pub payload: Vec<u8>,
+    pub time: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Violation {
+    pub path: String,
+    pub field: Option<String>,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeviceChanged {
+    pub version: i64,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub enum Response {
     Status(Status),
     Error(Error),
-    Device(Device),
+    Device(Box<Device>),
     AttributeValue(AttributeValue),
     ActionResult(ActionResult),
+    Hello(Hello),
+    Job(Job),
+    ActionProgress(ActionProgress),
+    AttributeUpdate(AttributeUpdate),
+    EventOccurrence(EventOccurrence),
+    Violation(Violation),
+    DeviceChanged(DeviceChanged),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResponseMessage {
+    pub id: i64,
+    pub response: Response,
 }
@@ -1,128 +1,530 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::env;
 use std::error::Error;
 use std::io;
 use std::net::{TcpListener, TcpStream};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
 use std::result::Result;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
 
+use avro_rs::types::Value;
 use avro_rs::{from_avro_datum, from_value, to_avro_datum, Reader, Schema, Writer};
 
 use mdcs::avro;
-use mdcs::device::{AttributeFlags, Device, Member};
+use mdcs::device::{
+    validate, AttributeFlags, AttributeNotifier, Device, DeviceNotifier, EventEmitter, Identity,
+    JobContext, Member, Metadata,
+};
 
-use super::request::{self as req, Request};
-use super::response::{self as resp, Response};
+use super::pool::WorkerPool;
+use super::request::{self as req, Request, RequestMessage};
+use super::response::{self as resp, Response, ResponseMessage};
+use super::transport::{self, StreamReader, StreamWriter, Transport};
+use super::{message_id, CAPABILITY_DEVICE_CHANGES, PROTOCOL_VERSION, SERVER_CAPABILITIES};
 
 pub struct Server {
-    device: Device,
+    name: String,
+    version: String,
+    shared: Arc<ServerShared>,
+    pool: WorkerPool,
+    connection: u64,
+    token: Option<String>,
+    authenticated: bool,
     signal_quit: bool,
+    deadline: Duration,
 }
 
-fn initial_connect() -> io::Result<TcpStream> {
-    let listener = TcpListener::bind("127.0.0.1:0")?;
-    let address = listener.local_addr()?;
-    println!("LISTENING {}:{}", address.ip(), address.port());
+/// Number of threads that read, write, and run device members unless the
+/// server is created with a different number.
+const DEFAULT_WORKERS: usize = 4;
 
-    match listener.accept() {
-        Ok((stream, address)) => {
-            println!("ACCEPTED {}:{}", address.ip(), address.port());
-            Ok(stream)
+/// Time an attribute read or write may take before its member is considered
+/// hung, unless the server is created with a different deadline.
+const DEFAULT_DEADLINE: Duration = Duration::from_secs(10);
+
+/// Interval at which running attribute calls are checked against their
+/// deadline.
+const WATCHDOG_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Time the result of a finished job is kept for if nobody collects it.
+const JOB_RETENTION: Duration = Duration::from_secs(600);
+
+/// Time an unauthenticated peer has to complete the handshake.
+const AUTHENTICATION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Socket a connection was accepted on.
+enum Socket {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Socket {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            Socket::Tcp(stream) => stream.set_read_timeout(timeout),
+            #[cfg(unix)]
+            Socket::Unix(stream) => stream.set_read_timeout(timeout),
         }
-        Err(error) => Err(error),
     }
 }
 
-impl Server {
-    pub fn new(device: Device) -> Server {
-        Server {
+type Accepted = (StreamReader, StreamWriter, Option<Socket>);
+
+/// Description of a single device member.
+enum Described {
+    Attribute(resp::Attribute),
+    Action(resp::Action),
+    Event(resp::Event),
+}
+
+fn describe_metadata(metadata: Metadata) -> resp::Metadata {
+    resp::Metadata {
+        description: metadata.description,
+        unit: metadata.unit,
+        minimum: metadata.minimum,
+        maximum: metadata.maximum,
+        step: metadata.step,
+        labels: metadata.labels.into_iter().collect(),
+        precision: metadata
+            .precision
+            .map(|precision| i32::try_from(precision).unwrap_or(i32::MAX)),
+    }
+}
+
+fn describe_identity(identity: Identity) -> resp::Identity {
+    resp::Identity {
+        name: identity.name,
+        vendor: identity.vendor,
+        model: identity.model,
+        serial: identity.serial,
+        firmware_version: identity.firmware_version,
+        description: identity.description,
+    }
+}
+
+/// Wait for exclusive access to a member, a member that panicked while
+/// holding its lock is already faulted so the poison is ignored.
+fn acquire(lock: &Mutex<()>) -> MutexGuard<'_, ()> {
+    lock.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Extract the message from a panic payload.
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+/// Listens for the node to connect using the transport it requested.
+enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+    Stdio(bool),
+}
+
+impl Listener {
+    fn bind(transport: Transport) -> io::Result<Listener> {
+        match transport {
+            Transport::Tcp => {
+                let listener = TcpListener::bind("127.0.0.1:0")?;
+                let address = listener.local_addr()?;
+                println!("LISTENING {}:{}", address.ip(), address.port());
+
+                Ok(Listener::Tcp(listener))
+            }
+            #[cfg(unix)]
+            Transport::Unix => {
+                let path = env::var_os(transport::SOCKET_VARIABLE).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "missing plugin socket path")
+                })?;
+
+                let listener = UnixListener::bind(&path)?;
+                println!("LISTENING {}", Path::new(&path).display());
+
+                Ok(Listener::Unix(listener))
+            }
+            #[cfg(not(unix))]
+            Transport::Unix => Err(io::Error::new(
+                io::ErrorKind::Other,
+                "Unix domain sockets are not supported on this platform",
+            )),
+            Transport::Stdio => Ok(Listener::Stdio(false)),
+        }
+    }
+
+    /// Wait for the next connection, standard input and output can only be
+    /// used for a single connection.
+    fn accept(&mut self) -> io::Result<Option<Accepted>> {
+        match self {
+            Listener::Tcp(listener) => {
+                let (stream, address) = listener.accept()?;
+                eprintln!("ACCEPTED {}:{}", address.ip(), address.port());
+
+                let socket = Socket::Tcp(stream.try_clone()?);
+                Ok(Some((
+                    Box::new(stream.try_clone()?),
+                    Box::new(stream),
+                    Some(socket),
+                )))
+            }
+            #[cfg(unix)]
+            Listener::Unix(listener) => {
+                let (stream, _address) = listener.accept()?;
+                eprintln!("ACCEPTED");
+
+                let socket = Socket::Unix(stream.try_clone()?);
+                Ok(Some((
+                    Box::new(stream.try_clone()?),
+                    Box::new(stream),
+                    Some(socket),
+                )))
+            }
+            Listener::Stdio(used) => {
+                if *used {
+                    return Ok(None);
+                }
+
+                *used = true;
+                Ok(Some((Box::new(io::stdin()), Box::new(io::stdout()), None)))
+            }
+        }
+    }
+}
+
+/// Device members shared by the request workers.
+struct ServerShared {
+    device: Arc<Device>,
+    locks: Mutex<HashMap<String, Arc<Mutex<()>>>>,
+    /// Members that panicked, a member inserted at the same path afterwards
+    /// is not faulted.
+    faulted: Mutex<HashMap<String, Weak<Member>>>,
+    jobs: Mutex<HashMap<i64, Job>>,
+    next_job: AtomicI64,
+    watched: Mutex<HashMap<String, Weak<Member>>>,
+    subscribers: Mutex<HashMap<String, HashMap<u64, Sender<ResponseMessage>>>>,
+    /// Connection of a node that asked to be told about device changes.
+    node: Mutex<Option<Sender<ResponseMessage>>>,
+    calls: Mutex<HashMap<u64, Call>>,
+    next_call: AtomicU64,
+}
+
+/// Attribute read or write running on a worker, answered by the watchdog if
+/// the member does not return before the deadline.
+struct Call {
+    path: String,
+    member: Weak<Member>,
+    deadline: Instant,
+    id: i64,
+    sender: Sender<ResponseMessage>,
+}
+
+/// Action running in the background.
+struct Job {
+    path: String,
+    context: JobContext,
+    event: Option<Vec<u8>>,
+    outcome: Option<Response>,
+    finished: Option<Instant>,
+}
+
+impl Job {
+    fn describe(&self, id: i64) -> resp::Job {
+        let (state, message) = match self.outcome {
+            None => (resp::JobState::Running, None),
+            Some(Response::Error(ref error)) if self.context.is_cancelled() => {
+                (resp::JobState::Cancelled, Some(error.message.clone()))
+            }
+            Some(Response::Error(ref error)) => {
+                (resp::JobState::Failed, Some(error.message.clone()))
+            }
+            Some(Response::Violation(ref violation)) => {
+                (resp::JobState::Failed, Some(violation.message.clone()))
+            }
+            Some(_) => (resp::JobState::Completed, None),
+        };
+
+        resp::Job {
+            job: id,
+            path: self.path.clone(),
+            state,
+            progress: self.context.progress(),
+            message,
+            event: self.event.clone(),
+        }
+    }
+}
+
+fn job_not_found(job: i64) -> Response {
+    Response::Error(resp::Error {
+        message: format!("Job not found: {}", job),
+        path: None,
+    })
+}
+
+impl ServerShared {
+    fn new(device: Arc<Device>) -> ServerShared {
+        ServerShared {
             device,
-            signal_quit: false,
+            locks: Mutex::new(HashMap::new()),
+            faulted: Mutex::new(HashMap::new()),
+            jobs: Mutex::new(HashMap::new()),
+            next_job: AtomicI64::new(1),
+            watched: Mutex::new(HashMap::new()),
+            subscribers: Mutex::new(HashMap::new()),
+            node: Mutex::new(None),
+            calls: Mutex::new(HashMap::new()),
+            next_call: AtomicU64::new(0),
         }
     }
 
-    fn signal(&mut self, signal: &req::Signal) -> Response {
-        match signal {
-            req::Signal::Quit => {
-                self.signal_quit = true;
-                Response::Status(resp::Status::Ok)
+    fn is_faulted(&self, path: &str, member: &Arc<Member>) -> bool {
+        let faulted = self.faulted.lock().unwrap_or_else(PoisonError::into_inner);
+        match faulted.get(path).and_then(Weak::upgrade) {
+            Some(faulted) => Arc::ptr_eq(&faulted, member),
+            None => false,
+        }
+    }
+
+    fn set_faulted(&self, path: &str, member: &Arc<Member>, message: &str) {
+        eprintln!("Member {} faulted: {}", path, message);
+
+        let mut faulted = self.faulted.lock().unwrap_or_else(PoisonError::into_inner);
+        faulted.retain(|_path, member| member.strong_count() > 0);
+        faulted.insert(path.to_string(), Arc::downgrade(member));
+    }
+
+    /// Start watching a request that reads or writes an attribute, actions
+    /// may run for a long time and are not watched.
+    fn begin_call(
+        &self,
+        request: &Request,
+        id: i64,
+        sender: &Sender<ResponseMessage>,
+        deadline: Duration,
+    ) -> Option<u64> {
+        let path = match request {
+            Request::ReadAttribute(args) => &args.path,
+            Request::WriteAttribute(args) => &args.path,
+            _ => return None,
+        };
+
+        let member = self.device.get(path)?;
+        let call = self.next_call.fetch_add(1, Ordering::SeqCst);
+        let mut calls = self.calls.lock().unwrap_or_else(PoisonError::into_inner);
+        calls.insert(
+            call,
+            Call {
+                path: path.clone(),
+                member: Arc::downgrade(&member),
+                deadline: Instant::now() + deadline,
+                id,
+                sender: sender.clone(),
+            },
+        );
+
+        Some(call)
+    }
+
+    /// Stop watching a request and return whether it still has to be
+    /// answered.
+    fn end_call(&self, call: Option<u64>) -> bool {
+        match call {
+            Some(call) => {
+                let mut calls = self.calls.lock().unwrap_or_else(PoisonError::into_inner);
+                calls.remove(&call).is_some()
             }
+            None => true,
         }
     }
 
-    fn describe_device(&mut self) -> Response {
+    /// Fault the members of requests that missed their deadline and answer
+    /// the requests with an error, the workers stay stuck in the members.
+    fn expire_calls(&self) {
+        let now = Instant::now();
+        let expired: Vec<Call> = {
+            let mut calls = self.calls.lock().unwrap_or_else(PoisonError::into_inner);
+            let ids: Vec<u64> = calls
+                .iter()
+                .filter(|(_call, call)| call.deadline <= now)
+                .map(|(call, _call)| *call)
+                .collect();
+
+            ids.iter().filter_map(|call| calls.remove(call)).collect()
+        };
+
+        for call in expired {
+            let message = "did not return before its deadline";
+            if let Some(member) = call.member.upgrade() {
+                self.set_faulted(&call.path, &member, message);
+            }
+
+            let response = Response::Error(resp::Error {
+                message: format!("Member faulted: {}", message),
+                path: Some(call.path),
+            });
+
+            let _ = call.sender.send(ResponseMessage {
+                id: call.id,
+                response,
+            });
+        }
+    }
+
+    /// Lock used to serialize calls to members that may not be accessed
+    /// concurrently, created the first time a path is used since members can
+    /// be added while the server is running.
+    fn member_lock(&self, path: &str, concurrent: bool) -> Option<Arc<Mutex<()>>> {
+        if concurrent {
+            return None;
+        }
+
+        let mut locks = self.locks.lock().unwrap_or_else(PoisonError::into_inner);
+        Some(locks.entry(path.to_string()).or_default().clone())
+    }
+
+    /// Describe the members of the device, or only those at or below a path.
+    fn describe_device(&self, path: Option<&str>) -> Response {
         let mut attributes: Vec<resp::Attribute> = vec![];
         let mut actions: Vec<resp::Action> = vec![];
+        let mut events: Vec<resp::Event> = vec![];
 
-        for member in self.device.iter() {
-            match member {
-                (path, Member::Attribute(attribute)) => {
-                    let mut flags: Vec<String> = vec![];
-                    for flag in attribute.flags() {
-                        flags.push(match flag {
-                            AttributeFlags::Read => "read".to_string(),
-                            AttributeFlags::Write => "write".to_string(),
-                        });
-                    }
+        let members = match self.device.subtree(path.unwrap_or("")) {
+            Ok(members) => members,
+            Err(error) => {
+                return Response::Error(resp::Error {
+                    message: format!("{}", error),
+                    path: path.map(String::from),
+                });
+            }
+        };
 
-                    let schema = match serde_json::to_string(&attribute.schema()) {
-                        Ok(schema) => schema,
-                        Err(error) => {
-                            return Response::Error(resp::Error {
-                                message: format!("Failed to serialize attribute schema: {}", error),
-                                path: Some(path.clone()),
-                            });
-                        }
-                    };
-
-                    attributes.push(resp::Attribute {
-                        path: path.clone(),
-                        flags,
-                        schema,
-                    });
-                }
-                (path, Member::Action(action)) => {
-                    let input_schema = match serde_json::to_string(&action.input_schema()) {
-                        Ok(schema) => schema,
-                        Err(error) => {
-                            return Response::Error(resp::Error {
-                                message: format!(
-                                    "Failed to serialize action input schema: {}",
-                                    error
-                                ),
-                                path: Some(path.clone()),
-                            });
-                        }
-                    };
-
-                    let output_schema = match serde_json::to_string(&action.output_schema()) {
-                        Ok(schema) => schema,
-                        Err(error) => {
-                            return Response::Error(resp::Error {
-                                message: format!(
-                                    "Failed to serialize action output schema: {}",
-                                    error
-                                ),
-                                path: Some(path.clone()),
-                            });
-                        }
-                    };
-
-                    actions.push(resp::Action {
-                        path: path.clone(),
-                        input_schema,
-                        output_schema,
-                    })
+        let version = i64::try_from(members.version()).unwrap_or(i64::MAX);
+        for (path, member) in members {
+            if self.is_faulted(&path, &member) {
+                continue;
+            }
+
+            let description = panic::catch_unwind(AssertUnwindSafe(|| {
+                ServerShared::describe_member(&path, &member)
+            }));
+
+            match description {
+                Ok(Ok(Described::Attribute(attribute))) => attributes.push(attribute),
+                Ok(Ok(Described::Action(action))) => actions.push(action),
+                Ok(Ok(Described::Event(event))) => events.push(event),
+                Ok(Err(response)) => return response,
+                Err(payload) => {
+                    let message = panic_message(&*payload);
+                    self.set_faulted(&path, &member, &message);
                 }
             }
         }
 
-        Response::Device(resp::Device {
+        Response::Device(Box::new(resp::Device {
             attributes,
             actions,
-        })
+            events,
+            identity: describe_identity(self.device.identity()),
+            version,
+        }))
     }
 
-    fn read_attribute(&mut self, args: &req::ReadAttribute) -> Response {
+    fn describe_member(path: &str, member: &Member) -> Result<Described, Response> {
+        match member {
+            Member::Attribute(attribute) => {
+                let mut flags: Vec<String> = vec![];
+                for flag in attribute.flags() {
+                    flags.push(match flag {
+                        AttributeFlags::Read => "read".to_string(),
+                        AttributeFlags::Write => "write".to_string(),
+                    });
+                }
+
+                let schema = serde_json::to_string(&attribute.schema()).map_err(|error| {
+                    Response::Error(resp::Error {
+                        message: format!("Failed to serialize attribute schema: {}", error),
+                        path: Some(path.to_string()),
+                    })
+                })?;
+
+                Ok(Described::Attribute(resp::Attribute {
+                    path: path.to_string(),
+                    flags,
+                    schema,
+                    metadata: describe_metadata(attribute.metadata()),
+                }))
+            }
+            Member::Action(action) => {
+                let input_schema =
+                    serde_json::to_string(&action.input_schema()).map_err(|error| {
+                        Response::Error(resp::Error {
+                            message: format!("Failed to serialize action input schema: {}", error),
+                            path: Some(path.to_string()),
+                        })
+                    })?;
+
+                let output_schema =
+                    serde_json::to_string(&action.output_schema()).map_err(|error| {
+                        Response::Error(resp::Error {
+                            message: format!("Failed to serialize action output schema: {}", error),
+                            path: Some(path.to_string()),
+                        })
+                    })?;
+
+                let progress_schema =
+                    serde_json::to_string(&action.progress_schema()).map_err(|error| {
+                        Response::Error(resp::Error {
+                            message: format!(
+                                "Failed to serialize action progress schema: {}",
+                                error
+                            ),
+                            path: Some(path.to_string()),
+                        })
+                    })?;
+
+                Ok(Described::Action(resp::Action {
+                    path: path.to_string(),
+                    input_schema,
+                    output_schema,
+                    progress_schema,
+                    metadata: describe_metadata(action.metadata()),
+                }))
+            }
+            Member::Event(event) => {
+                let schema = serde_json::to_string(&event.schema()).map_err(|error| {
+                    Response::Error(resp::Error {
+                        message: format!("Failed to serialize event schema: {}", error),
+                        path: Some(path.to_string()),
+                    })
+                })?;
+
+                Ok(Described::Event(resp::Event {
+                    path: path.to_string(),
+                    schema,
+                }))
+            }
+        }
+    }
+
+    fn read_attribute(&self, args: &req::ReadAttribute) -> Response {
         // retrieve the device attribute
-        let attribute = match self.device.get(&args.path) {
+        let member = self.device.get(&args.path);
+        let attribute = match member.as_deref() {
             Some(Member::Attribute(attribute)) => attribute,
             Some(_) => {
                 return Response::Error(resp::Error {
@@ -150,6 +552,8 @@ impl Server {
         let time = avro::timestamp();
 
         // read the attribute value
+        let lock = self.member_lock(&args.path, attribute.concurrent());
+        let _guard = lock.as_deref().map(acquire);
         let value = match attribute.read() {
             Ok(value) => value,
             Err(error) => {
@@ -175,9 +579,10 @@ impl Server {
         Response::AttributeValue(resp::AttributeValue { value, time })
     }
 
-    fn write_attribute(&mut self, args: &req::WriteAttribute) -> Response {
+    fn write_attribute(&self, args: &req::WriteAttribute) -> Response {
         // retrieve the device attribute
-        let attribute = match self.device.get(&args.path) {
+        let member = self.device.get(&args.path);
+        let attribute = match member.as_deref() {
             Some(Member::Attribute(attribute)) => attribute,
             Some(_) => {
                 return Response::Error(resp::Error {
@@ -216,10 +621,21 @@ impl Server {
             }
         };
 
+        // check the value follows the attribute constraints
+        if let Err(violation) = validate(&attribute.constraints(), &decoded_value) {
+            return Response::Violation(resp::Violation {
+                path: args.path.clone(),
+                field: violation.field,
+                message: violation.message,
+            });
+        }
+
         // retrieve the current system time
         let time = avro::timestamp();
 
         // write the attribute value
+        let lock = self.member_lock(&args.path, attribute.concurrent());
+        let _guard = lock.as_deref().map(acquire);
         if let Err(error) = attribute.write(decoded_value) {
             return Response::Error(resp::Error {
                 message: format!("Failed to write attribute: {}", error),
@@ -230,9 +646,10 @@ impl Server {
         Response::AttributeValue(resp::AttributeValue { value, time })
     }
 
-    fn run_action(&mut self, args: &req::RunAction) -> Response {
+    fn run_action(&self, args: &req::RunAction, context: &JobContext) -> Response {
         // retrieve the device action
-        let action = match self.device.get(&args.path) {
+        let member = self.device.get(&args.path);
+        let action = match member.as_deref() {
             Some(Member::Action(action)) => action,
             Some(_) => {
                 return Response::Error(resp::Error {
@@ -263,11 +680,22 @@ impl Server {
             }
         };
 
+        // check the input follows the action constraints
+        if let Err(violation) = validate(&action.input_constraints(), &input_value) {
+            return Response::Violation(resp::Violation {
+                path: args.path.clone(),
+                field: violation.field,
+                message: violation.message,
+            });
+        }
+
         // record start time
         let start = avro::timestamp();
 
         // run the action
-        let output_value = match action.run(input_value) {
+        let lock = self.member_lock(&args.path, action.concurrent());
+        let guard = lock.as_deref().map(acquire);
+        let output_value = match action.run_job(input_value, context) {
             Ok(output) => output,
             Err(error) => {
                 return Response::Error(resp::Error {
@@ -279,6 +707,7 @@ impl Server {
 
         // record end time
         let end = avro::timestamp();
+        drop(guard);
 
         // encode the output value
         let output_schema = action.output_schema();
@@ -295,56 +724,705 @@ impl Server {
         Response::ActionResult(resp::ActionResult { output, start, end })
     }
 
-    fn process_request(&mut self, request: &Request) -> Response {
-        match request {
-            Request::Signal(signal) => self.signal(signal),
-            Request::DescribeDevice(_) => self.describe_device(),
-            Request::ReadAttribute(args) => self.read_attribute(args),
-            Request::WriteAttribute(args) => self.write_attribute(args),
-            Request::RunAction(args) => self.run_action(args),
+    /// Send the connection updates of an attribute or occurrences of an event
+    /// until it unsubscribes or closes.
+    fn subscribe(
+        shared: &Arc<ServerShared>,
+        path: &str,
+        connection: u64,
+        sender: &Sender<ResponseMessage>,
+    ) -> Response {
+        let weak = Arc::downgrade(shared);
+        let response = shared.isolate(path, |shared| shared.watch_member(path, weak));
+
+        if let Response::Status(_) = response {
+            let mut subscribers = shared
+                .subscribers
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            subscribers
+                .entry(path.to_string())
+                .or_default()
+                .insert(connection, sender.clone());
         }
+
+        response
     }
 
-    pub fn run(&mut self) -> Result<(), Box<dyn Error>> {
-        // parse the plugin message schemas
-        let request_schema = Schema::parse_str(include_str!("request.avsc"))
-            .expect("Failed to parse request message schema");
+    fn unsubscribe(&self, path: &str, connection: u64) -> Response {
+        let mut subscribers = self
+            .subscribers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let removed = subscribers
+            .get_mut(path)
+            .and_then(|connections| connections.remove(&connection));
 
-        let response_schema = Schema::parse_str(include_str!("response.avsc"))
-            .expect("Failed to parse response message schema");
+        match removed {
+            Some(_) => Response::Status(resp::Status::Ok),
+            None => Response::Error(resp::Error {
+                message: "Not subscribed".to_string(),
+                path: Some(path.to_string()),
+            }),
+        }
+    }
 
-        // wait for someone to connect
-        let stream = initial_connect()?;
+    /// Forget the subscriptions of a closed connection.
+    fn drop_subscriptions(&self, connection: u64) {
+        let mut subscribers = self
+            .subscribers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        for connections in subscribers.values_mut() {
+            connections.remove(&connection);
+        }
+    }
 
-        // create plugin request reader and response writer
-        let reader = Reader::with_schema(&request_schema, &stream)?;
-        let mut writer = Writer::new(&response_schema, &stream);
+    /// Ask an attribute to report its changes or an event to report its
+    /// occurrences, members are only watched once and what they report is
+    /// shared by every subscriber.
+    fn watch_member(&self, path: &str, shared: Weak<ServerShared>) -> Response {
+        let member = match self.device.get(path) {
+            Some(member) if matches!(*member, Member::Action(_)) => {
+                return Response::Error(resp::Error {
+                    message: "Path does not refer to an attribute or event".to_string(),
+                    path: Some(path.to_string()),
+                });
+            }
+            Some(member) => member,
+            None => {
+                return Response::Error(resp::Error {
+                    message: "Path not found".to_string(),
+                    path: Some(path.to_string()),
+                });
+            }
+        };
 
-        // process requests
-        for value in reader {
-            // parse the request
-            let value = value.expect("Failed to read request");
-            let request = from_value::<Request>(&value);
-
-            // process the request into a response
-            let response = match request {
-                Ok(request) => self.process_request(&request),
-                Err(error) => Response::Error(resp::Error {
-                    message: String::from(format!("{}", error)),
+        // a member that replaced a watched one has to be watched again
+        let mut watched = self.watched.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(watched) = watched.get(path).and_then(Weak::upgrade) {
+            if Arc::ptr_eq(&watched, &member) {
+                return Response::Status(resp::Status::Ok);
+            }
+        }
+
+        let publish_path = path.to_string();
+        let publish_member = Arc::downgrade(&member);
+        let publish = move |value| {
+            if let Some(shared) = shared.upgrade() {
+                shared.publish(&publish_path, &publish_member, value);
+            }
+        };
+
+        let result = match *member {
+            Member::Attribute(ref attribute) => {
+                let lock = self.member_lock(path, attribute.concurrent());
+                let _guard = lock.as_deref().map(acquire);
+                attribute
+                    .watch(AttributeNotifier::new(publish))
+                    .map_err(|error| format!("Failed to watch attribute: {}", error))
+            }
+            Member::Event(ref event) => event
+                .listen(EventEmitter::new(publish))
+                .map_err(|error| format!("Failed to listen to event: {}", error)),
+            Member::Action(_) => Ok(()),
+        };
+
+        if let Err(message) = result {
+            return Response::Error(resp::Error {
+                message,
+                path: Some(path.to_string()),
+            });
+        }
+
+        watched.insert(path.to_string(), Arc::downgrade(&member));
+        Response::Status(resp::Status::Ok)
+    }
+
+    /// Send an attribute value or event occurrence reported by a member to
+    /// its subscribers, unless the member was removed from the device.
+    fn publish(&self, path: &str, member: &Weak<Member>, value: Value) {
+        let member = match (self.device.get(path), member.upgrade()) {
+            (Some(current), Some(member)) if Arc::ptr_eq(&current, &member) => current,
+            _ => return,
+        };
+
+        let (schema, is_event) = match *member {
+            Member::Attribute(ref attribute) => (attribute.schema(), false),
+            Member::Event(ref event) => (event.schema(), true),
+            Member::Action(_) => return,
+        };
+
+        let value = match to_avro_datum(&schema, value) {
+            Ok(bytes) => bytes,
+            Err(error) => {
+                eprintln!("Failed to serialize update for {}: {}", path, error);
+                return;
+            }
+        };
+
+        let time = avro::timestamp();
+        let mut subscribers = self
+            .subscribers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        if let Some(connections) = subscribers.get_mut(path) {
+            // updates are not associated with a request
+            connections.retain(|_connection, sender| {
+                let response = if is_event {
+                    Response::EventOccurrence(resp::EventOccurrence {
+                        path: path.to_string(),
+                        payload: value.clone(),
+                        time,
+                    })
+                } else {
+                    Response::AttributeUpdate(resp::AttributeUpdate {
+                        path: path.to_string(),
+                        value: value.clone(),
+                        time,
+                    })
+                };
+
+                sender.send(ResponseMessage { id: 0, response }).is_ok()
+            });
+        }
+    }
+
+    /// Tell the node that members were added to or removed from the device.
+    fn device_changed(&self, version: u64) {
+        let node = self.node.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(ref sender) = *node {
+            let response = Response::DeviceChanged(resp::DeviceChanged {
+                version: i64::try_from(version).unwrap_or(i64::MAX),
+            });
+
+            // changes are not associated with a request
+            let _ = sender.send(ResponseMessage { id: 0, response });
+        }
+    }
+
+    /// Encode an intermediate result emitted by a running action.
+    fn encode_progress(&self, path: &str, event: Value) -> Option<Vec<u8>> {
+        let member = self.device.get(path)?;
+        let action = match *member {
+            Member::Action(ref action) => action,
+            _ => return None,
+        };
+
+        match to_avro_datum(&action.progress_schema(), event) {
+            Ok(bytes) => Some(bytes),
+            Err(error) => {
+                eprintln!("Failed to serialize progress event for {}: {}", path, error);
+                None
+            }
+        }
+    }
+
+    /// Create a context that sends the events emitted by an action to the
+    /// node as responses to the request that is running it.
+    fn streaming_context(
+        shared: &Arc<ServerShared>,
+        path: &str,
+        id: i64,
+        sender: &Sender<ResponseMessage>,
+    ) -> JobContext {
+        let weak = Arc::downgrade(shared);
+        let path = path.to_string();
+        let sender = Mutex::new(sender.clone());
+
+        JobContext::with_sink(move |event| {
+            let value = match weak.upgrade() {
+                Some(shared) => shared.encode_progress(&path, event),
+                None => None,
+            };
+
+            if let Some(value) = value {
+                let response = Response::ActionProgress(resp::ActionProgress {
+                    value,
+                    time: avro::timestamp(),
+                });
+
+                let sender = sender.lock().unwrap_or_else(PoisonError::into_inner);
+                let _ = sender.send(ResponseMessage { id, response });
+            }
+        })
+    }
+
+    /// Run an action on its own thread and return a handle that can be used
+    /// to follow it, the job outlives the connection that started it.
+    fn start_job(shared: &Arc<ServerShared>, args: &req::RunAction) -> Response {
+        match shared.device.get(&args.path).as_deref() {
+            Some(Member::Action(_)) => {}
+            Some(_) => {
+                return Response::Error(resp::Error {
+                    message: "Path does not refer to an action".to_string(),
+                    path: Some(args.path.clone()),
+                });
+            }
+            None => {
+                return Response::Error(resp::Error {
+                    message: "Path not found".to_string(),
+                    path: Some(args.path.clone()),
+                });
+            }
+        }
+
+        let id = shared.next_job.fetch_add(1, Ordering::SeqCst);
+
+        // keep the latest event for clients polling the job status
+        let weak = Arc::downgrade(shared);
+        let path = args.path.clone();
+        let context = JobContext::with_sink(move |event| {
+            if let Some(shared) = weak.upgrade() {
+                let event = shared.encode_progress(&path, event);
+                let mut jobs = shared.jobs.lock().unwrap_or_else(PoisonError::into_inner);
+                if let (Some(job), Some(event)) = (jobs.get_mut(&id), event) {
+                    job.event = Some(event);
+                }
+            }
+        });
+
+        let job = Job {
+            path: args.path.clone(),
+            context: context.clone(),
+            event: None,
+            outcome: None,
+            finished: None,
+        };
+
+        let status = job.describe(id);
+        {
+            let mut jobs = shared.jobs.lock().unwrap_or_else(PoisonError::into_inner);
+
+            // forget finished jobs nobody collected the result of
+            jobs.retain(|_id, job| match job.finished {
+                Some(finished) => finished.elapsed() < JOB_RETENTION,
+                None => true,
+            });
+
+            jobs.insert(id, job);
+        }
+
+        let args = req::RunAction {
+            path: args.path.clone(),
+            input: args.input.clone(),
+            background: false,
+        };
+
+        let shared = shared.clone();
+        thread::spawn(move || {
+            let outcome = shared.isolate(&args.path, |shared| shared.run_action(&args, &context));
+
+            let mut jobs = shared.jobs.lock().unwrap_or_else(PoisonError::into_inner);
+            if let Some(job) = jobs.get_mut(&id) {
+                job.outcome = Some(outcome);
+                job.finished = Some(Instant::now());
+            }
+        });
+
+        Response::Job(status)
+    }
+
+    fn job_status(&self, args: &req::JobStatus) -> Response {
+        let jobs = self.jobs.lock().unwrap_or_else(PoisonError::into_inner);
+        match jobs.get(&args.job) {
+            Some(job) => Response::Job(job.describe(args.job)),
+            None => job_not_found(args.job),
+        }
+    }
+
+    fn job_result(&self, args: &req::JobResult) -> Response {
+        let mut jobs = self.jobs.lock().unwrap_or_else(PoisonError::into_inner);
+        let finished = match jobs.get(&args.job) {
+            Some(job) => job.outcome.is_some(),
+            None => return job_not_found(args.job),
+        };
+
+        // the result can only be collected once
+        if !finished {
+            return Response::Job(jobs[&args.job].describe(args.job));
+        }
+
+        match jobs.remove(&args.job).and_then(|job| job.outcome) {
+            Some(outcome) => outcome,
+            None => job_not_found(args.job),
+        }
+    }
+
+    fn cancel_job(&self, args: &req::CancelJob) -> Response {
+        let jobs = self.jobs.lock().unwrap_or_else(PoisonError::into_inner);
+        match jobs.get(&args.job) {
+            Some(job) => {
+                job.context.cancel();
+                Response::Job(job.describe(args.job))
+            }
+            None => job_not_found(args.job),
+        }
+    }
+
+    /// Call into a device member so that a panic in its implementation is
+    /// reported as an error and the member is marked as faulted instead of
+    /// taking down the whole plugin.
+    fn isolate<F>(&self, path: &str, handler: F) -> Response
+    where
+        F: FnOnce(&ServerShared) -> Response,
+    {
+        // missing members are reported by the handler
+        let member = self.device.get(path);
+        if let Some(ref member) = member {
+            if self.is_faulted(path, member) {
+                return Response::Error(resp::Error {
+                    message: "Member faulted".to_string(),
+                    path: Some(path.to_string()),
+                });
+            }
+        }
+
+        match panic::catch_unwind(AssertUnwindSafe(|| handler(self))) {
+            Ok(response) => response,
+            Err(payload) => {
+                let message = panic_message(&*payload);
+                if let Some(ref member) = member {
+                    self.set_faulted(path, member, &message);
+                }
+
+                Response::Error(resp::Error {
+                    message: format!("Member faulted: {}", message),
+                    path: Some(path.to_string()),
+                })
+            }
+        }
+    }
+
+    /// Process a request on a worker, actions it runs use the given context.
+    fn process_request(&self, request: &Request, context: &JobContext) -> Response {
+        match request {
+            Request::DescribeDevice(args) => self.describe_device(args.path.as_deref()),
+            Request::ReadAttribute(args) => {
+                self.isolate(&args.path, |shared| shared.read_attribute(args))
+            }
+            Request::WriteAttribute(args) => {
+                self.isolate(&args.path, |shared| shared.write_attribute(args))
+            }
+            Request::RunAction(args) => {
+                self.isolate(&args.path, |shared| shared.run_action(args, context))
+            }
+            Request::JobStatus(args) => self.job_status(args),
+            Request::JobResult(args) => self.job_result(args),
+            Request::CancelJob(args) => self.cancel_job(args),
+            Request::Signal(_)
+            | Request::Hello(_)
+            | Request::Subscribe(_)
+            | Request::Unsubscribe(_) => Response::Error(resp::Error {
+                message: "Request must be processed by the server".to_string(),
+                path: None,
+            }),
+        }
+    }
+}
+
+fn write_responses(stream_writer: StreamWriter, receiver: Receiver<ResponseMessage>) {
+    let response_schema = match Schema::parse_str(include_str!("response.avsc")) {
+        Ok(schema) => schema,
+        Err(error) => {
+            eprintln!("Failed to parse response message schema: {}", error);
+            return;
+        }
+    };
+
+    let mut writer = Writer::new(&response_schema, stream_writer);
+
+    // send responses until every request has been processed
+    while let Ok(message) = receiver.recv() {
+        let id = message.id;
+        if let Err(error) = writer.append_ser(message) {
+            // tell the node why it is not getting the response it expected
+            eprintln!("Failed to serialize response: {}", error);
+            let fallback = writer.append_ser(ResponseMessage {
+                id,
+                response: Response::Error(resp::Error {
+                    message: format!("Failed to serialize response: {}", error),
                     path: None,
                 }),
-            };
+            });
+
+            if fallback.is_err() {
+                continue;
+            }
+        }
+
+        if let Err(error) = writer.flush() {
+            eprintln!("Failed to send response: {}", error);
+            break;
+        }
+    }
+}
+
+impl Server {
+    pub fn new(name: &str, version: &str, device: Arc<Device>) -> Server {
+        Server::with_workers(name, version, device, DEFAULT_WORKERS)
+    }
+
+    /// Create a server that accesses device members from the given number of
+    /// worker threads.
+    pub fn with_workers(name: &str, version: &str, device: Arc<Device>, workers: usize) -> Server {
+        let shared = Arc::new(ServerShared::new(device));
+
+        // the device may outlive the server so it only keeps a weak reference
+        let weak = Arc::downgrade(&shared);
+        shared.device.watch(DeviceNotifier::new(move |version| {
+            if let Some(shared) = weak.upgrade() {
+                shared.device_changed(version);
+            }
+        }));
+
+        // detect members that hang instead of waiting for every worker to
+        // be stuck in one
+        let watchdog = Arc::downgrade(&shared);
+        thread::spawn(move || loop {
+            thread::sleep(WATCHDOG_INTERVAL);
+            match watchdog.upgrade() {
+                Some(shared) => shared.expire_calls(),
+                None => break,
+            }
+        });
+
+        Server {
+            name: name.to_string(),
+            version: version.to_string(),
+            shared,
+            pool: WorkerPool::new(workers),
+            connection: 0,
+            token: transport::take_token(),
+            authenticated: false,
+            signal_quit: false,
+            deadline: DEFAULT_DEADLINE,
+        }
+    }
+
+    /// Time attribute reads and writes may take before the member is marked
+    /// as faulted and the request fails.
+    pub fn with_deadline(mut self, deadline: Duration) -> Server {
+        self.deadline = deadline;
+        self
+    }
+
+    fn hello(&mut self, args: &req::Hello, sender: &Sender<ResponseMessage>) -> Response {
+        if let Some(ref token) = self.token {
+            if !transport::tokens_match(token, &args.token) {
+                return Response::Error(resp::Error {
+                    message: "Authentication failed".to_string(),
+                    path: None,
+                });
+            }
+        }
+
+        if args.protocol_version != PROTOCOL_VERSION {
+            return Response::Error(resp::Error {
+                message: format!(
+                    "Unsupported protocol version {}, plugin {} {} requires version {}",
+                    args.protocol_version, self.name, self.version, PROTOCOL_VERSION
+                ),
+                path: None,
+            });
+        }
+
+        let capabilities: Vec<String> = SERVER_CAPABILITIES
+            .iter()
+            .filter(|capability| args.capabilities.iter().any(|c| c == *capability))
+            .map(|capability| capability.to_string())
+            .collect();
+
+        if capabilities.iter().any(|c| c == CAPABILITY_DEVICE_CHANGES) {
+            let mut node = self
+                .shared
+                .node
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            *node = Some(sender.clone());
+        }
+
+        // only a complete handshake authenticates the connection
+        self.authenticated = true;
+
+        Response::Hello(resp::Hello {
+            protocol_version: PROTOCOL_VERSION,
+            plugin_name: self.name.clone(),
+            plugin_version: self.version.clone(),
+            capabilities,
+        })
+    }
+
+    /// Process requests that change the connection state, every other request
+    /// is queued on the worker pool.
+    fn process_request(&mut self, message: RequestMessage, sender: &Sender<ResponseMessage>) {
+        let id = message.id;
 
-            // send the response
-            writer
-                .append_ser(response)
-                .expect("Failed to serialize response");
+        // the handshake authenticates the connection
+        let response = match message.request {
+            Request::Hello(ref args) => self.hello(args, sender),
+            _ if !self.authenticated => Response::Error(resp::Error {
+                message: "Expected Hello request".to_string(),
+                path: None,
+            }),
+            Request::Signal(req::Signal::Quit) => {
+                self.signal_quit = true;
+                Response::Status(resp::Status::Ok)
+            }
+
+            // heartbeats are answered while every worker runs a long action
+            Request::Signal(req::Signal::Ping) => Response::Status(resp::Status::Ok),
+            Request::Subscribe(ref args) => {
+                ServerShared::subscribe(&self.shared, &args.path, self.connection, sender)
+            }
+            Request::Unsubscribe(ref args) => self.shared.unsubscribe(&args.path, self.connection),
+            Request::RunAction(ref args) if args.background => {
+                ServerShared::start_job(&self.shared, args)
+            }
+            request => {
+                let context = match request {
+                    Request::RunAction(ref args) => {
+                        ServerShared::streaming_context(&self.shared, &args.path, id, sender)
+                    }
+                    _ => JobContext::new(),
+                };
+
+                let shared = self.shared.clone();
+                let sender = sender.clone();
+                let deadline = self.deadline;
+                self.pool.execute(move || {
+                    let call = shared.begin_call(&request, id, &sender, deadline);
+                    let response = shared.process_request(&request, &context);
+
+                    // the watchdog already answered requests that hung
+                    if shared.end_call(call) {
+                        let _ = sender.send(ResponseMessage { id, response });
+                    }
+                });
+
+                return;
+            }
+        };
+
+        let _ = sender.send(ResponseMessage { id, response });
+    }
+
+    fn serve_connection(
+        &mut self,
+        stream_reader: StreamReader,
+        stream_writer: StreamWriter,
+        mut socket: Option<Socket>,
+    ) -> Result<(), Box<dyn Error>> {
+        let request_schema = Schema::parse_str(include_str!("request.avsc"))?;
+        self.authenticated = false;
+        self.connection += 1;
+
+        // do not let an unauthenticated peer stall the server
+        if let Some(ref socket) = socket {
+            socket.set_read_timeout(Some(AUTHENTICATION_TIMEOUT))?;
+        }
+
+        // responses are sent as requests complete which is not necessarily the
+        // order in which they were received
+        let (sender, receiver) = channel();
+        let writer_handle = thread::spawn(move || write_responses(stream_writer, receiver));
 
-            writer.flush().expect("Failed to flush Avro writer");
+        let result = self.read_requests(&request_schema, stream_reader, &sender, &mut socket);
+
+        // wait for outstanding requests to finish
+        self.shared.drop_subscriptions(self.connection);
+        *self
+            .shared
+            .node
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = None;
+        drop(sender);
+        let _ = writer_handle.join();
+
+        result
+    }
+
+    fn read_requests(
+        &mut self,
+        request_schema: &Schema,
+        stream_reader: StreamReader,
+        sender: &Sender<ResponseMessage>,
+        socket: &mut Option<Socket>,
+    ) -> Result<(), Box<dyn Error>> {
+        let reader = Reader::with_schema(request_schema, stream_reader)?;
+
+        for value in reader {
+            // a corrupt frame leaves the stream in an unknown state
+            let value = value?;
+            match from_value::<RequestMessage>(&value) {
+                Ok(message) => self.process_request(message, sender),
+                Err(error) => {
+                    let _ = sender.send(ResponseMessage {
+                        id: message_id(&value).unwrap_or(0),
+                        response: Response::Error(resp::Error {
+                            message: format!("{}", error),
+                            path: None,
+                        }),
+                    });
+                }
+            }
+
+            // drop connections that fail to authenticate
+            if !self.authenticated {
+                return Err("Connection failed to authenticate".into());
+            }
+
+            // the node may stay idle once it is authenticated
+            if let Some(socket) = socket.take() {
+                socket.set_read_timeout(None)?;
+            }
 
             // quit if necessary
             if self.signal_quit {
-                println!("QUIT");
+                eprintln!("QUIT");
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Process requests from an already established connection until it is
+    /// closed or fails.
+    pub fn serve(
+        &mut self,
+        stream_reader: StreamReader,
+        stream_writer: StreamWriter,
+    ) -> Result<(), Box<dyn Error>> {
+        self.serve_connection(stream_reader, stream_writer, None)
+    }
+
+    pub fn run(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.token.is_none() {
+            if !transport::allow_insecure() {
+                return Err(format!(
+                    "No connection secret provided, set {} or {}=1 to accept any connection",
+                    transport::TOKEN_VARIABLE,
+                    transport::INSECURE_VARIABLE
+                )
+                .into());
+            }
+
+            eprintln!("No connection secret provided, accepting any connection");
+        }
+
+        // wait for the node to connect
+        let transport = Transport::from_env()?;
+        let mut listener = Listener::bind(transport)?;
+
+        while let Some((stream_reader, stream_writer, socket)) = listener.accept()? {
+            if let Err(error) = self.serve_connection(stream_reader, stream_writer, socket) {
+                eprintln!("Connection closed: {}", error);
+            }
+
+            // keep waiting for the node after rejecting an intruder
+            if self.authenticated {
                 break;
             }
         }
@@ -353,3 +1431,199 @@ impl Server {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use mdcs::device::{ComputedAttribute, ConstantAttribute};
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn hello() -> Request {
+        Request::Hello(req::Hello {
+            protocol_version: PROTOCOL_VERSION,
+            node_version: "0.0.0".to_string(),
+            capabilities: vec![],
+            token: String::new(),
+        })
+    }
+
+    #[test]
+    fn ping_is_answered_while_workers_are_busy() {
+        let (release, blocked) = channel::<()>();
+        let blocked = Mutex::new(blocked);
+        let attribute = ComputedAttribute::new(Schema::Long, move || {
+            let _ = blocked.lock().unwrap().recv();
+            Ok(Value::Long(0))
+        });
+
+        let device = Arc::new(Device::new());
+        device
+            .insert("blocked", Member::Attribute(Box::new(attribute)))
+            .unwrap();
+
+        let mut server = Server::with_workers("test", "0.0.0", device, 2);
+        let (sender, receiver) = channel();
+
+        let message = RequestMessage {
+            id: 1,
+            request: hello(),
+        };
+        server.process_request(message, &sender);
+        let response = receiver.recv_timeout(TIMEOUT).unwrap();
+        assert!(matches!(response.response, Response::Hello(_)));
+
+        // occupy every worker, the attribute is not concurrent so the second
+        // read waits for the first one in its worker
+        for id in 2..4 {
+            let message = RequestMessage {
+                id,
+                request: Request::ReadAttribute(req::ReadAttribute {
+                    path: "blocked".to_string(),
+                }),
+            };
+            server.process_request(message, &sender);
+        }
+
+        let message = RequestMessage {
+            id: 4,
+            request: Request::Signal(req::Signal::Ping),
+        };
+        server.process_request(message, &sender);
+
+        let response = receiver.recv_timeout(TIMEOUT).unwrap();
+        assert_eq!(response.id, 4);
+        assert!(matches!(
+            response.response,
+            Response::Status(resp::Status::Ok)
+        ));
+
+        // the reads complete once the attribute stops blocking
+        release.send(()).unwrap();
+        release.send(()).unwrap();
+        let mut ids = vec![
+            receiver.recv_timeout(TIMEOUT).unwrap().id,
+            receiver.recv_timeout(TIMEOUT).unwrap().id,
+        ];
+        ids.sort();
+        assert_eq!(ids, [2, 3]);
+    }
+
+    fn read(shared: &ServerShared, path: &str) -> Response {
+        let request = Request::ReadAttribute(req::ReadAttribute {
+            path: path.to_string(),
+        });
+
+        shared.process_request(&request, &JobContext::new())
+    }
+
+    fn described(shared: &ServerShared) -> Vec<String> {
+        match shared.describe_device(None) {
+            Response::Device(device) => device
+                .attributes
+                .into_iter()
+                .map(|attribute| attribute.path)
+                .collect(),
+            response => panic!("unexpected describe response: {:?}", response),
+        }
+    }
+
+    #[test]
+    fn reinserted_member_is_not_faulted() {
+        let attribute = ComputedAttribute::new(Schema::Long, || panic!("broken sensor"));
+        let device = Arc::new(Device::new());
+        device
+            .insert("value", Member::Attribute(Box::new(attribute)))
+            .unwrap();
+
+        let shared = ServerShared::new(device.clone());
+        match read(&shared, "value") {
+            Response::Error(error) => assert_eq!(error.message, "Member faulted: broken sensor"),
+            response => panic!("unexpected read response: {:?}", response),
+        }
+
+        match read(&shared, "value") {
+            Response::Error(error) => assert_eq!(error.message, "Member faulted"),
+            response => panic!("unexpected read response: {:?}", response),
+        }
+
+        assert!(described(&shared).is_empty());
+
+        // replacing the member clears the fault
+        device.remove("value").unwrap();
+        let attribute = ConstantAttribute::new(Schema::Long, Value::Long(1));
+        device
+            .insert("value", Member::Attribute(Box::new(attribute)))
+            .unwrap();
+
+        assert_eq!(described(&shared), ["value"]);
+        match read(&shared, "value") {
+            Response::Error(error) => assert!(!error.message.contains("faulted")),
+            Response::AttributeValue(_) => {}
+            response => panic!("unexpected read response: {:?}", response),
+        }
+    }
+
+    #[test]
+    fn hung_member_is_faulted() {
+        let (release, blocked) = channel::<()>();
+        let blocked = Mutex::new(blocked);
+        let attribute = ComputedAttribute::new(Schema::Long, move || {
+            let _ = blocked.lock().unwrap().recv();
+            Ok(Value::Long(0))
+        });
+
+        let device = Arc::new(Device::new());
+        device
+            .insert("hung", Member::Attribute(Box::new(attribute)))
+            .unwrap();
+
+        let mut server = Server::with_workers("test", "0.0.0", device, 2)
+            .with_deadline(Duration::from_millis(100));
+        let (sender, receiver) = channel();
+
+        let message = RequestMessage {
+            id: 1,
+            request: hello(),
+        };
+        server.process_request(message, &sender);
+        receiver.recv_timeout(TIMEOUT).unwrap();
+
+        let read = |id| RequestMessage {
+            id,
+            request: Request::ReadAttribute(req::ReadAttribute {
+                path: "hung".to_string(),
+            }),
+        };
+
+        // the read is answered once it misses the deadline
+        server.process_request(read(2), &sender);
+        let response = receiver.recv_timeout(TIMEOUT).unwrap();
+        assert_eq!(response.id, 2);
+        match response.response {
+            Response::Error(error) => {
+                assert_eq!(
+                    error.message,
+                    "Member faulted: did not return before its deadline"
+                )
+            }
+            response => panic!("unexpected read response: {:?}", response),
+        }
+
+        // later requests fail right away without occupying a worker
+        server.process_request(read(3), &sender);
+        let response = receiver.recv_timeout(TIMEOUT).unwrap();
+        assert_eq!(response.id, 3);
+        match response.response {
+            Response::Error(error) => assert_eq!(error.message, "Member faulted"),
+            response => panic!("unexpected read response: {:?}", response),
+        }
+
+        assert!(described(&server.shared).is_empty());
+
+        // the late result of the hung read is not sent
+        release.send(()).unwrap();
+        assert!(receiver.recv_timeout(Duration::from_millis(200)).is_err());
+    }
+}
@@ -0,0 +1,133 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use serde::{Deserialize, Serialize};
+
+/// Environment variable used to tell the plugin which transport to use.
+pub const TRANSPORT_VARIABLE: &str = "MDCS_PLUGIN_TRANSPORT";
+
+/// Environment variable holding the Unix domain socket path the plugin
+/// should listen on.
+pub const SOCKET_VARIABLE: &str = "MDCS_PLUGIN_SOCKET";
+
+/// Environment variable holding the one-time secret the node presents when
+/// connecting to the plugin.
+pub const TOKEN_VARIABLE: &str = "MDCS_PLUGIN_TOKEN";
+
+/// Environment variable that lets a plugin started without a secret, such as
+/// during development, accept any connection when set to `1`.
+pub const INSECURE_VARIABLE: &str = "MDCS_PLUGIN_INSECURE";
+
+/// Receiving half of a plugin connection.
+pub type StreamReader = Box<dyn Read + Send>;
+
+/// Sending half of a plugin connection.
+pub type StreamWriter = Box<dyn Write + Send>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    /// TCP socket on the loopback interface.
+    Tcp,
+
+    /// Unix domain socket in a private runtime directory.
+    Unix,
+
+    /// Standard input and output of the plugin process.
+    Stdio,
+}
+
+impl Default for Transport {
+    fn default() -> Transport {
+        Transport::Tcp
+    }
+}
+
+impl Transport {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Transport::Tcp => "tcp",
+            Transport::Unix => "unix",
+            Transport::Stdio => "stdio",
+        }
+    }
+
+    /// Determine the transport requested by the node from the environment.
+    pub fn from_env() -> io::Result<Transport> {
+        match env::var(TRANSPORT_VARIABLE) {
+            Ok(name) => match name.as_str() {
+                "tcp" => Ok(Transport::Tcp),
+                "unix" => Ok(Transport::Unix),
+                "stdio" => Ok(Transport::Stdio),
+                _ => Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("unknown plugin transport: {}", name),
+                )),
+            },
+            Err(_) => Ok(Transport::Tcp),
+        }
+    }
+}
+
+/// Create a directory only accessible by the current user to hold a plugin
+/// socket.
+pub fn create_runtime_dir() -> io::Result<PathBuf> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let base = env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(env::temp_dir);
+
+    let path = base.join(format!(
+        "mdcs-{}-{}",
+        process::id(),
+        COUNTER.fetch_add(1, Ordering::SeqCst)
+    ));
+
+    let mut builder = fs::DirBuilder::new();
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+
+    builder.create(&path)?;
+    Ok(path)
+}
+
+/// Generate a random secret for authenticating a plugin connection.
+pub fn generate_token() -> io::Result<String> {
+    let mut bytes = [0u8; 32];
+    File::open("/dev/urandom")?.read_exact(&mut bytes)?;
+
+    Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// Retrieve the connection secret from the environment and remove it so
+/// processes started by the plugin do not inherit it.
+pub fn take_token() -> Option<String> {
+    let token = env::var(TOKEN_VARIABLE).ok();
+    env::remove_var(TOKEN_VARIABLE);
+
+    token
+}
+
+/// Check whether the plugin was explicitly allowed to run without a secret.
+pub fn allow_insecure() -> bool {
+    matches!(env::var(INSECURE_VARIABLE), Ok(ref value) if value == "1")
+}
+
+/// Compare secrets in constant time.
+pub fn tokens_match(expected: &str, actual: &str) -> bool {
+    expected.len() == actual.len()
+        && expected
+            .bytes()
+            .zip(actual.bytes())
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
@@ -0,0 +1,325 @@
+//! Feed random and corrupted input to both ends of the plugin protocol and
+//! verify neither side panics.
+
+use std::io::{self, Cursor};
+use std::panic;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, Once, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+use avro_rs::types::Value;
+use avro_rs::{Schema, Writer};
+
+use mdcs::device::{Device, FnAction, Member, ValueAttribute};
+use mdcs_node::plugin::request::{self as req, Request, RequestMessage};
+use mdcs_node::plugin::response::{self as resp, Response, ResponseMessage};
+use mdcs_node::plugin::{Client, Server};
+
+const ITERATIONS: u64 = 256;
+
+static PANICKED: AtomicBool = AtomicBool::new(false);
+static HOOK: Once = Once::new();
+static SERIAL: Mutex<()> = Mutex::new(());
+
+/// Record panics on any thread, including the client worker threads.
+fn install_panic_hook() {
+    HOOK.call_once(|| {
+        let default_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            PANICKED.store(true, Ordering::SeqCst);
+            default_hook(info);
+        }));
+    });
+}
+
+/// Run one test at a time so a panic is only reported by the test that
+/// caused it.
+fn exclusive() -> MutexGuard<'static, ()> {
+    let guard = SERIAL.lock().unwrap_or_else(PoisonError::into_inner);
+    install_panic_hook();
+    PANICKED.store(false, Ordering::SeqCst);
+
+    guard
+}
+
+/// Read a zigzag encoded variable length integer.
+fn read_long(bytes: &[u8], position: &mut usize) -> i64 {
+    let mut value: u64 = 0;
+    let mut shift = 0;
+
+    while let Some(byte) = bytes.get(*position) {
+        *position += 1;
+        if shift < 64 {
+            value |= u64::from(byte & 0x7f) << shift;
+        }
+
+        shift += 7;
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+
+    (value >> 1) as i64 ^ -((value & 1) as i64)
+}
+
+/// Length of the object container header made of the magic bytes, the
+/// metadata map and the sync marker, so only the messages get corrupted.
+fn header_length(bytes: &[u8]) -> usize {
+    let mut position = 4;
+
+    loop {
+        let mut count = read_long(bytes, &mut position);
+        if count == 0 || position >= bytes.len() {
+            break;
+        }
+
+        // negative counts are followed by the size of the block in bytes
+        if count < 0 {
+            count = -count;
+            read_long(bytes, &mut position);
+        }
+
+        for _ in 0..count {
+            let key = read_long(bytes, &mut position);
+            position += key.max(0) as usize;
+            let value = read_long(bytes, &mut position);
+            position += value.max(0) as usize;
+        }
+    }
+
+    (position + 16).min(bytes.len())
+}
+
+/// Small deterministic xorshift generator so failures can be reproduced.
+struct Random(u64);
+
+impl Random {
+    fn new(seed: u64) -> Random {
+        Random(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, limit: usize) -> usize {
+        (self.next() % limit as u64) as usize
+    }
+
+    fn bytes(&mut self, length: usize) -> Vec<u8> {
+        (0..length).map(|_| self.next() as u8).collect()
+    }
+
+    /// Overwrite random bytes after the first `skip` bytes.
+    fn corrupt(&mut self, bytes: &mut Vec<u8>, skip: usize) {
+        if bytes.len() <= skip {
+            return;
+        }
+
+        for _ in 0..=self.below(8) {
+            let index = skip + self.below(bytes.len() - skip);
+            bytes[index] = self.next() as u8;
+        }
+
+        if self.below(4) == 0 {
+            let length = skip + self.below(bytes.len() - skip);
+            bytes.truncate(length);
+        }
+    }
+}
+
+fn request_stream() -> Vec<u8> {
+    let schema = Schema::parse_str(include_str!("../src/plugin/request.avsc")).unwrap();
+    let mut writer = Writer::new(&schema, Vec::new());
+
+    let requests = vec![
+        Request::Hello(req::Hello {
+            protocol_version: mdcs_node::plugin::PROTOCOL_VERSION,
+            node_version: "0.0.0".to_string(),
+            capabilities: vec![],
+            token: String::new(),
+        }),
+        Request::Signal(req::Signal::Ping),
+        Request::DescribeDevice(req::DescribeDevice { path: None }),
+        Request::DescribeDevice(req::DescribeDevice {
+            path: Some("path".to_string()),
+        }),
+        Request::ReadAttribute(req::ReadAttribute {
+            path: "missing".to_string(),
+        }),
+        Request::ReadAttribute(req::ReadAttribute {
+            path: "value".to_string(),
+        }),
+        Request::WriteAttribute(req::WriteAttribute {
+            path: "value".to_string(),
+            value: vec![2],
+        }),
+        Request::RunAction(req::RunAction {
+            path: "action".to_string(),
+            input: vec![2],
+            background: false,
+        }),
+        Request::WriteAttribute(req::WriteAttribute {
+            path: "missing".to_string(),
+            value: vec![1, 2, 3],
+        }),
+        Request::RunAction(req::RunAction {
+            path: "missing".to_string(),
+            input: vec![4, 5, 6],
+            background: false,
+        }),
+        Request::RunAction(req::RunAction {
+            path: "missing".to_string(),
+            input: vec![],
+            background: true,
+        }),
+        Request::JobStatus(req::JobStatus { job: 1 }),
+        Request::JobResult(req::JobResult { job: 1 }),
+        Request::CancelJob(req::CancelJob { job: 1 }),
+        Request::Subscribe(req::Subscribe {
+            path: "missing".to_string(),
+        }),
+        Request::Unsubscribe(req::Unsubscribe {
+            path: "missing".to_string(),
+        }),
+    ];
+
+    for (id, request) in requests.into_iter().enumerate() {
+        writer
+            .append_ser(RequestMessage {
+                id: id as i64,
+                request,
+            })
+            .unwrap();
+
+        writer.flush().unwrap();
+    }
+
+    writer.into_inner()
+}
+
+fn response_stream() -> Vec<u8> {
+    let schema = Schema::parse_str(include_str!("../src/plugin/response.avsc")).unwrap();
+    let mut writer = Writer::new(&schema, Vec::new());
+
+    let responses = vec![
+        Response::Status(resp::Status::Ok),
+        Response::Error(resp::Error {
+            message: "error".to_string(),
+            path: Some("path".to_string()),
+        }),
+        Response::AttributeValue(resp::AttributeValue {
+            value: vec![1, 2, 3],
+            time: 0,
+        }),
+        Response::EventOccurrence(resp::EventOccurrence {
+            path: "path".to_string(),
+            payload: vec![1, 2, 3],
+            time: 0,
+        }),
+        Response::DeviceChanged(resp::DeviceChanged { version: 1 }),
+    ];
+
+    for (id, response) in responses.into_iter().enumerate() {
+        writer
+            .append_ser(ResponseMessage {
+                id: id as i64 + 1,
+                response,
+            })
+            .unwrap();
+
+        writer.flush().unwrap();
+    }
+
+    writer.into_inner()
+}
+
+fn serve(input: Vec<u8>) {
+    let device = Arc::new(Device::new());
+    let attribute = ValueAttribute::new(Schema::Long, Value::Long(0));
+    device
+        .insert("value", Member::Attribute(Box::new(attribute)))
+        .unwrap();
+
+    let action = FnAction::new(Schema::Long, Schema::Long, Ok);
+    device
+        .insert("action", Member::Action(Box::new(action)))
+        .unwrap();
+
+    let mut server = Server::new("fuzz", "0.0.0", device);
+    let _ = server.serve(Box::new(Cursor::new(input)), Box::new(io::sink()));
+}
+
+fn request(input: Vec<u8>) {
+    let client = Client::new(Box::new(Cursor::new(input)), Box::new(io::sink()));
+    let timeout = Duration::from_millis(50);
+
+    for _ in 0..3 {
+        let _ = client.process_request(Request::Signal(req::Signal::Ping), timeout);
+    }
+
+    // give the reader thread a chance to consume the rest of the input
+    thread::sleep(Duration::from_millis(1));
+}
+
+#[test]
+fn server_survives_random_bytes() {
+    let _guard = exclusive();
+
+    for seed in 0..ITERATIONS {
+        let mut random = Random::new(seed);
+        let length = random.below(4096);
+        serve(random.bytes(length));
+    }
+
+    assert!(!PANICKED.load(Ordering::SeqCst));
+}
+
+#[test]
+fn server_survives_corrupted_requests() {
+    let _guard = exclusive();
+    let stream = request_stream();
+    let header = header_length(&stream);
+
+    for seed in 0..ITERATIONS {
+        let mut random = Random::new(seed);
+        let mut input = stream.clone();
+        random.corrupt(&mut input, header);
+        serve(input);
+    }
+
+    assert!(!PANICKED.load(Ordering::SeqCst));
+}
+
+#[test]
+fn client_survives_random_bytes() {
+    let _guard = exclusive();
+
+    for seed in 0..ITERATIONS {
+        let mut random = Random::new(seed);
+        let length = random.below(4096);
+        request(random.bytes(length));
+    }
+
+    assert!(!PANICKED.load(Ordering::SeqCst));
+}
+
+#[test]
+fn client_survives_corrupted_responses() {
+    let _guard = exclusive();
+    let stream = response_stream();
+    let header = header_length(&stream);
+
+    for seed in 0..ITERATIONS {
+        let mut random = Random::new(seed);
+        let mut input = stream.clone();
+        random.corrupt(&mut input, header);
+        request(input);
+    }
+
+    assert!(!PANICKED.load(Ordering::SeqCst));
+}
@@ -1,11 +1,13 @@
+use std::sync::Arc;
+
 use mdcs::device::Device;
 use mdcs_node::plugin::Server;
 use mdcs_node_host::platform_attributes;
 
 fn main() {
-    let mut device = Device::new();
-    platform_attributes(&mut device);
+    let device = Arc::new(Device::new());
+    platform_attributes(&device);
 
-    let mut server = Server::new(device);
+    let mut server = Server::new(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"), device);
     server.run().expect("Failed to run plugin server");
 }
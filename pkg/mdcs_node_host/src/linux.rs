@@ -1,10 +1,14 @@
+use std::fs;
 use std::result::Result;
 
 use avro_rs::schema::Schema;
 use avro_rs::types::Value;
 use sensors::{FeatureType, Sensors, SubfeatureType};
 
-use mdcs::device::{Attribute, Device, DeviceError, Member};
+use mdcs::device::{Attribute, Device, DeviceError, Identity, Member, Metadata};
+
+/// Directory where the kernel exposes the DMI tables of the computer.
+const DMI_DIRECTORY: &str = "/sys/class/dmi/id";
 
 struct TempAttribute {
     chip_address: i32,
@@ -20,6 +24,15 @@ impl Attribute for TempAttribute {
         true
     }
 
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            description: Some("Sensor temperature".to_string()),
+            unit: Some("degC".to_string()),
+            precision: Some(1),
+            ..Metadata::default()
+        }
+    }
+
     fn read(&self) -> Result<Value, DeviceError> {
         let sensors = Sensors::new();
         let chip = sensors
@@ -46,13 +59,40 @@ impl Attribute for TempAttribute {
     }
 }
 
-pub fn platform_attributes(device: &mut Device) {
+/// Read a DMI field, some are only readable by root and many are left empty
+/// or filled with placeholders by the manufacturer.
+fn dmi_field(field: &str) -> Option<String> {
+    let value = fs::read_to_string(format!("{}/{}", DMI_DIRECTORY, field)).ok()?;
+    let value = value.trim();
+    if value.is_empty() || value == "To Be Filled By O.E.M." {
+        return None;
+    }
+
+    Some(value.to_string())
+}
+
+pub fn platform_attributes(device: &Device) {
+    device.set_identity(Identity {
+        vendor: dmi_field("sys_vendor"),
+        model: dmi_field("product_name"),
+        serial: dmi_field("product_serial"),
+        firmware_version: dmi_field("bios_version"),
+        ..Identity::default()
+    });
+
     let sensors = Sensors::new();
 
     for chip in sensors {
-        let chip_name = chip.get_name().unwrap();
+        let chip_name = match chip.get_name() {
+            Ok(name) => name,
+            Err(error) => {
+                eprintln!("Skipping sensor chip without a name: {}", error);
+                continue;
+            }
+        };
         let chip_address = chip.address();
 
+        // each chip is a branch with its features below it
         for feature in chip {
             let feature_name = feature.name().to_string();
             let feature_number = feature.number();
@@ -66,8 +106,10 @@ pub fn platform_attributes(device: &mut Device) {
             };
 
             if let Some(attribute) = attribute.take() {
-                let path = format!("{}.{}", &chip_name, &feature_name);
-                device.insert(&path, Member::Attribute(attribute)).unwrap();
+                let path = format!("{}.{}", chip_name, feature_name);
+                if let Err(error) = device.insert(&path, Member::Attribute(attribute)) {
+                    eprintln!("Skipping sensor feature: {}", error);
+                }
             }
         }
     }
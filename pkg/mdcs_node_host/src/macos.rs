@@ -1,12 +1,50 @@
 use std::process::Command;
 use std::result::Result;
 
-use avro_rs::from_value;
 use avro_rs::schema::Schema;
 use avro_rs::types::Value;
 use serde::Deserialize;
 
-use mdcs::device::{Action, Attribute, Device, DeviceError, Member};
+use mdcs::device::{
+    Attribute, Constraint, Device, DeviceError, Identity, Member, Metadata, TypedAction,
+    TypedActionHandler,
+};
+use mdcs_derive::AvroSchema;
+
+fn ioreg_property(class: &str, property: &str) -> Result<String, DeviceError> {
+    let output = Command::new("/usr/sbin/ioreg")
+        .args(["-r", "-c", class, "-k", property, "-d", "1"])
+        .output()?;
+
+    let stdout =
+        String::from_utf8(output.stdout).map_err(|_e| "ioreg output not UTF-8 compatible")?;
+
+    let line = stdout
+        .lines()
+        .find(|line| line.contains(property))
+        .ok_or("ioreg output did not contain property name")?;
+
+    let wrap_chars: &[_] = &['"', '<', '>'];
+    let value = line
+        .split(" = ")
+        .nth(1)
+        .ok_or("ioreg output property line not in expected format")?
+        .trim_matches(wrap_chars);
+
+    Ok(value.to_string())
+}
+
+/// Read a property of the platform expert, which describes the computer
+/// itself.
+fn platform_property(property: &str) -> Option<String> {
+    match ioreg_property("IOPlatformExpertDevice", property) {
+        Ok(value) => Some(value),
+        Err(error) => {
+            eprintln!("Failed to read platform {}: {}", property, error);
+            None
+        }
+    }
+}
 
 #[derive(Debug)]
 struct IORegAttribute {
@@ -23,71 +61,47 @@ impl Attribute for IORegAttribute {
         true
     }
 
+    fn concurrent(&self) -> bool {
+        true
+    }
+
     fn read(&self) -> Result<Value, DeviceError> {
-        let output = Command::new("/usr/sbin/ioreg")
-            .args(&["-r", "-c", &self.class, "-k", &self.property, "-d", "1"])
-            .output()?;
-
-        let stdout =
-            String::from_utf8(output.stdout).map_err(|_e| "ioreg output not UTF-8 compatible")?;
-
-        let line = stdout
-            .lines()
-            .filter(|line| line.contains(&self.property))
-            .nth(0)
-            .ok_or("ioreg output did not contain property name")?;
-
-        let wrap_chars: &[_] = &['"', '<', '>'];
-        let value = line
-            .split(" = ")
-            .nth(1)
-            .ok_or("ioreg output property line not in expected format")?
-            .trim_matches(wrap_chars);
-
-        Ok(Value::String(value.to_string()))
+        let value = ioreg_property(&self.class, &self.property)?;
+        Ok(Value::String(value))
     }
 }
 
 #[derive(Debug)]
 struct SayAction {}
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, AvroSchema)]
 struct SaySettings {
     msg: String,
     voice: Option<String>,
     rate: Option<i32>,
 }
 
-impl Action for SayAction {
-    fn input_schema(&self) -> Schema {
-        let raw_schema = r#"
-            {
-                "type": "record",
-                "name": "SaySettings",
-                "fields": [
-                    {"name": "msg", "type": "string"},
-                    {"name": "voice", "type": ["null", "string"]},
-                    {"name": "rate", "type": ["null", "int"]},
-                ]
-            }
-        "#;
-
-        Schema::parse_str(raw_schema).expect("Failed to parse embedded schema")
+impl TypedActionHandler<SaySettings, ()> for SayAction {
+    fn input_constraints(&self) -> Vec<Constraint> {
+        vec![
+            Constraint::field("msg", vec![Constraint::length(Some(1), None)]),
+            Constraint::field("rate", vec![Constraint::range(Some(1.0), None)]),
+        ]
     }
 
-    fn output_schema(&self) -> Schema {
-        Schema::Null
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            description: Some("Speak a message using text to speech".to_string()),
+            ..Metadata::default()
+        }
     }
 
-    fn run(&self, input: Value) -> Result<Value, DeviceError> {
-        let settings =
-            from_value::<SaySettings>(&input).expect("Action input does not match schema");
-
+    fn run(&self, settings: SaySettings) -> Result<(), DeviceError> {
         let mut args: Vec<String> = vec![];
 
         if let Some(voice) = settings.voice {
             args.push("-v".to_string());
-            args.push(voice.clone());
+            args.push(voice);
         }
 
         if let Some(rate) = settings.rate {
@@ -95,21 +109,29 @@ impl Action for SayAction {
             args.push(rate.to_string());
         }
 
-        args.push(settings.msg.clone());
+        args.push(settings.msg);
         let output = Command::new("/usr/bin/say").args(args).output()?;
 
         if !output.status.success() {
-            let stderr = String::from_utf8(output.stdout)
-                .map_err(|_e| "ioreg output not UTF-8 compatible")?;
+            let stderr =
+                String::from_utf8(output.stderr).map_err(|_e| "say output not UTF-8 compatible")?;
 
             return Err(From::from(format!("say failed: {}", stderr.trim())));
         }
 
-        Ok(Value::Null)
+        Ok(())
     }
 }
 
-pub fn platform_attributes(device: &mut Device) {
+pub fn platform_attributes(device: &Device) {
+    // serial and model number
+    device.set_identity(Identity {
+        vendor: Some("Apple".to_string()),
+        model: platform_property("model"),
+        serial: platform_property("IOPlatformSerialNumber"),
+        ..Identity::default()
+    });
+
     // serial number
     let attribute = Box::new(IORegAttribute {
         class: "IOPlatformExpertDevice".to_string(),
@@ -131,6 +153,6 @@ pub fn platform_attributes(device: &mut Device) {
         .unwrap();
 
     // say
-    let action = Box::new(SayAction {});
+    let action = Box::new(TypedAction::new(SayAction {}).unwrap());
     device.insert("say", Member::Action(action)).unwrap();
 }